@@ -0,0 +1,126 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+
+// 客户端可被授予的权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    EcoRead,
+    EcoAlter,
+    EcoSet,
+    EcoTransfer,
+    EcoAccount,  // 开户, 冻结, 解冻, 注销
+    EcoHold,     // 冻结款的创建, 结算, 释放
+    EcoReverse,  // 冲正交易
+    EcoSchedule, // 定时支付, 创建时还需要对应操作的权限
+    Admin,       // 拥有全部权限
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::EcoRead => "eco:read",
+            Scope::EcoAlter => "eco:alter",
+            Scope::EcoSet => "eco:set",
+            Scope::EcoTransfer => "eco:transfer",
+            Scope::EcoAccount => "eco:account",
+            Scope::EcoHold => "eco:hold",
+            Scope::EcoReverse => "eco:reverse",
+            Scope::EcoSchedule => "eco:schedule",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eco:read" => Ok(Scope::EcoRead),
+            "eco:alter" => Ok(Scope::EcoAlter),
+            "eco:set" => Ok(Scope::EcoSet),
+            "eco:transfer" => Ok(Scope::EcoTransfer),
+            "eco:account" => Ok(Scope::EcoAccount),
+            "eco:hold" => Ok(Scope::EcoHold),
+            "eco:reverse" => Ok(Scope::EcoReverse),
+            "eco:schedule" => Ok(Scope::EcoSchedule),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(format!("unknown scope {}", s)),
+        }
+    }
+}
+
+// 已通过认证的游戏服务器身份
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    pub name: String,
+    pub scopes: HashSet<Scope>,
+    // 单次增减余额允许的最大绝对值, None 表示不限制
+    pub alter_limit: Option<i64>,
+}
+
+impl ClientIdentity {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+}
+
+// 解析形如 `a=x,b=y` 的按客户端名配置的列表
+fn parse_named_list(raw: &str) -> impl Iterator<Item = (&str, &str)> {
+    raw.split(',').filter(|s| !s.trim().is_empty()).map(|s| {
+        let (name, value) = s
+            .split_once('=')
+            .expect("illegal client config, expected name=value");
+        (name.trim(), value.trim())
+    })
+}
+
+// 解析客户端凭据配置, 返回 token -> 身份 的映射
+// tokens:       `lobby=token1,minigame=token2`
+// scopes:       `lobby=admin,minigame=eco:read|eco:alter`, 未列出的客户端没有任何权限
+// alter_limits: `minigame=1000`
+pub fn parse_client_credentials(
+    tokens: &str,
+    scopes: &str,
+    alter_limits: &str,
+) -> HashMap<String, ClientIdentity> {
+    let mut scopes: HashMap<&str, HashSet<Scope>> = parse_named_list(scopes)
+        .map(|(name, scopes)| {
+            let scopes = scopes
+                .split('|')
+                .map(|scope| scope.trim().parse().unwrap())
+                .collect();
+            (name, scopes)
+        })
+        .collect();
+    let alter_limits: HashMap<&str, i64> = parse_named_list(alter_limits)
+        .map(|(name, limit)| (name, limit.parse().expect("illegal client alter limit")))
+        .collect();
+    parse_named_list(tokens)
+        .map(|(name, token)| {
+            (
+                token.to_string(),
+                ClientIdentity {
+                    name: name.to_string(),
+                    scopes: scopes.remove(name).unwrap_or_default(),
+                    alter_limit: alter_limits.get(name).copied(),
+                },
+            )
+        })
+        .collect()
+}
+
+// 从握手请求中取出客户端携带的token
+// 优先读取 `Authorization: Bearer <token>` 头, 其次读取 `?token=<token>` 查询参数
+pub fn extract_token(headers: &HeaderMap, query: &HashMap<String, String>) -> Option<String> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string())
+        .or_else(|| query.get("token").cloned())
+}
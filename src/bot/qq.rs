@@ -7,25 +7,59 @@ use ricq::{
     client::{Connector, DefaultConnector},
     ext::common::after_login,
     handler::{Handler, QEvent},
-    msg::MessageChain,
+    msg::{elem::Text, MessageChain},
     Client, Device, LoginDeviceLocked, LoginNeedCaptcha, LoginResponse, LoginSuccess,
     LoginUnknownStatus, Protocol,
 };
 use tokio_util::codec::{FramedRead, LinesCodec};
 use tracing::info;
 
+use crate::{
+    message::{Message, MessageType},
+    FineState,
+};
+
+// 超级用户在群里发送 `/notice <服务器名> <内容>` 向本实例上连接的该服务器发送通知
+const NOTICE_COMMAND: &str = "/notice ";
+
 pub struct FineHandler {
     super_users: Vec<u64>,
     allowed_groups: Vec<u64>,
+    fine_state: Arc<FineState>,
 }
 
 impl FineHandler {
-    pub fn new(super_users: Vec<u64>, allowed_groups: Vec<u64>) -> Self {
+    pub fn new(
+        super_users: Vec<u64>,
+        allowed_groups: Vec<u64>,
+        fine_state: Arc<FineState>,
+    ) -> Self {
         Self {
             super_users,
             allowed_groups,
+            fine_state,
         }
     }
+
+    // 把通知作为 admin_server_notice 发给指定服务器, 返回回复到群里的结果
+    fn send_notice(&self, command: &str) -> String {
+        let Some((server_name, text)) = command.trim().split_once(' ') else {
+            return "usage: /notice <server_name> <text>".to_string();
+        };
+        let notice = Message {
+            message_type: MessageType::AdminServerNotice,
+            request_id: None,
+            data: serde_json::json!({ "text": text.trim(), "source": "qq" }),
+        };
+        let (delivered, failed) = self
+            .fine_state
+            .connections
+            .send_to_server(server_name, notice);
+        format!(
+            "sent to {} connection(s) of {}, failed: {:?}",
+            delivered, server_name, failed
+        )
+    }
 }
 
 #[async_trait]
@@ -37,9 +71,13 @@ impl Handler for FineHandler {
                     "MESSAGE (GROUP={}): {}",
                     m.inner.group_code, m.inner.elements
                 );
-                let mut msg_chain = MessageChain::default();
-                msg_chain.push(m.inner.elements.0);
                 if self.super_users.contains(&(m.inner.from_uin as u64)) {
+                    let mut msg_chain = MessageChain::default();
+                    let content = m.inner.elements.to_string();
+                    match content.strip_prefix(NOTICE_COMMAND) {
+                        Some(command) => msg_chain.push(Text::new(self.send_notice(command))),
+                        None => msg_chain.push(m.inner.elements.0),
+                    }
                     m.client
                         .send_group_message(m.inner.group_code, msg_chain)
                         .await
@@ -77,10 +115,11 @@ pub async fn qq_bot_client(
     password: String,
     super_users: Vec<u64>,
     allowed_groups: Vec<u64>,
+    fine_state: Arc<FineState>,
 ) {
     let mut seed = StdRng::seed_from_u64(uin as u64);
     let device = Device::random_with_rng(&mut seed);
-    let f_handler = FineHandler::new(super_users, allowed_groups);
+    let f_handler = FineHandler::new(super_users, allowed_groups, fine_state);
     let client = Arc::new(Client::new(device, Protocol::IPad.into(), f_handler));
 
     let handle = tokio::spawn({
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, fs};
+
+use serde::Deserialize;
+
+use crate::error::FineError;
+
+// 请求未指定货币时使用的默认货币, 其数据沿用单货币时期的redis key
+pub const DEFAULT_CURRENCY: &str = "credit";
+
+// 经济系统配置, 从 ECONOMY_CONFIG 指向的json文件读取
+#[derive(Debug, Deserialize)]
+pub struct EconomyConfig {
+    // 货币名 -> 货币规则
+    #[serde(default)]
+    pub currencies: HashMap<String, CurrencyRule>,
+}
+
+// 单个货币的规则
+#[derive(Debug, Clone, Deserialize)]
+pub struct CurrencyRule {
+    // 余额是否允许为负
+    #[serde(default)]
+    pub allow_negative: bool,
+    // 是否允许用户之间转账
+    #[serde(default = "default_true")]
+    pub transferable: bool,
+    // 各操作的金额限制
+    #[serde(default)]
+    pub limits: OperationLimits,
+    // 转账手续费, 不填表示不收取
+    #[serde(default)]
+    pub transfer_fee: Option<TransferFeeConfig>,
+}
+
+// 转账手续费规则, 由转出方在转账金额之外支付
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferFeeConfig {
+    // 收取手续费的系统账户, 不存在时自动开户
+    pub system_account: String,
+    // 按转账金额划分的区间, 使用第一个命中的区间
+    #[serde(default)]
+    pub brackets: Vec<FeeBracket>,
+}
+
+// 一个金额区间内的手续费: max(金额 * rate_bps / 10000, min_fee)
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeeBracket {
+    // 闭区间的转账金额范围, 不填表示不限制
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    // 费率, 单位为万分之一
+    #[serde(default)]
+    pub rate_bps: i64,
+    #[serde(default)]
+    pub min_fee: i64,
+}
+
+impl TransferFeeConfig {
+    // 计算一笔转账的手续费, 没有命中的区间时不收取
+    pub fn fee_for(&self, amount: i64) -> Result<i64, FineError> {
+        let bracket = self.brackets.iter().find(|bracket| {
+            bracket.min_amount.is_none_or(|min| amount >= min)
+                && bracket.max_amount.is_none_or(|max| amount <= max)
+        });
+        let bracket = match bracket {
+            Some(bracket) => bracket,
+            None => return Ok(0),
+        };
+        let rate_fee = i64::try_from(amount as i128 * bracket.rate_bps as i128 / 10000)
+            .map_err(|_| FineError::AmountOverflow)?;
+        Ok(rate_fee.max(bracket.min_fee))
+    }
+}
+
+// 各操作的金额限制, 转账与增减按金额的绝对值判断, 设置按设置后的余额判断
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OperationLimits {
+    #[serde(default)]
+    pub set: AmountLimit,
+    #[serde(default)]
+    pub alter: AmountLimit,
+    #[serde(default)]
+    pub transfer: AmountLimit,
+}
+
+// 闭区间的金额限制, 不填表示不限制
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AmountLimit {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl AmountLimit {
+    pub fn check(&self, amount: i64) -> Result<(), FineError> {
+        if let Some(min) = self.min {
+            if amount < min {
+                return Err(FineError::InvalidAmount(format!(
+                    "{} is less than minimum {}",
+                    amount, min
+                )));
+            }
+        }
+        if let Some(max) = self.max {
+            if amount > max {
+                return Err(FineError::InvalidAmount(format!(
+                    "{} is greater than maximum {}",
+                    amount, max
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for CurrencyRule {
+    fn default() -> Self {
+        CurrencyRule {
+            allow_negative: false,
+            transferable: true,
+            limits: OperationLimits::default(),
+            transfer_fee: None,
+        }
+    }
+}
+
+impl EconomyConfig {
+    // 读取配置文件, 未配置路径时只有默认货币
+    pub fn load(path: Option<&str>) -> Self {
+        let mut config = match path {
+            Some(path) => {
+                let raw = fs::read_to_string(path).expect("failed to read economy config");
+                serde_json::from_str(&raw).expect("illegal economy config")
+            }
+            None => EconomyConfig {
+                currencies: HashMap::new(),
+            },
+        };
+        config
+            .currencies
+            .entry(DEFAULT_CURRENCY.to_string())
+            .or_default();
+        config
+    }
+
+    pub fn currency(&self, currency: &str) -> Option<&CurrencyRule> {
+        self.currencies.get(currency)
+    }
+}
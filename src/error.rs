@@ -0,0 +1,170 @@
+use std::fmt;
+
+use redis::RedisError;
+
+use crate::{message::ecosystem::BatchEntryResult, storage};
+
+// 服务内部的错误类型, 每种错误对应一个稳定的错误码返回给客户端
+#[derive(Debug)]
+pub enum FineError {
+    // 连接未携带有效的客户端token
+    Unauthorized,
+    // 客户端没有执行该操作的权限
+    Forbidden(String),
+    // 报文外层结构无法解析
+    InvalidMessage(serde_json::Error),
+    // 不支持的报文类型, 例如客户端发来了返回类型的报文
+    UnknownMessageType,
+    // 报文载荷与报文类型不匹配
+    InvalidPayload(serde_json::Error),
+    UserNotFound(String),
+    AccountExists(String),
+    AccountFrozen(String),
+    AccountClosed(String),
+    // 注销账户前余额必须为0
+    AccountNotEmpty(String),
+    UnknownCurrency(String),
+    NotTransferable(String),
+    // 金额不符合规则
+    InvalidAmount(String),
+    InsufficientCredit,
+    // 余额计算溢出
+    AmountOverflow,
+    SelfTransfer,
+    // 冻结款不存在或已过期
+    HoldNotFound(String),
+    TransactionNotFound(String),
+    // 交易已被冲正过
+    AlreadyReversed(String),
+    // 交易缺少冲正所需的信息, 例如旧版本写入的设置余额
+    NotReversible(String),
+    // 批量请求为空或条目过多
+    InvalidBatch(String),
+    // all_or_nothing 模式的批量请求有失败的项, 全部未生效, 附带各项的结果
+    BatchRejected(Vec<BatchEntryResult>),
+    // 定时任务的执行时间或周期不合法
+    InvalidSchedule(String),
+    ScheduledPaymentNotFound(String),
+    // 单个连接订阅的用户数超过上限
+    TooManySubscriptions,
+    // 幂等key已被内容不同的请求使用过
+    IdempotencyKeyReused(String),
+    // 乐观并发冲突重试次数耗尽
+    TooManyConflicts,
+    Storage(RedisError),
+    // 重试后仍无法连接到 redis
+    StorageUnavailable,
+    // 请求发出后连接出错, 无法确定是否已经生效, 且请求不能安全地重放
+    OutcomeUnknown,
+    // redis中的数据无法解析
+    BrokenRecord(serde_json::Error),
+}
+
+impl FineError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FineError::Unauthorized => "unauthorized",
+            FineError::Forbidden(_) => "forbidden",
+            FineError::InvalidMessage(_) => "invalid_message",
+            FineError::UnknownMessageType => "unknown_message_type",
+            FineError::InvalidPayload(_) => "invalid_payload",
+            FineError::UserNotFound(_) => "user_not_found",
+            FineError::AccountExists(_) => "account_exists",
+            FineError::AccountFrozen(_) => "account_frozen",
+            FineError::AccountClosed(_) => "account_closed",
+            FineError::AccountNotEmpty(_) => "account_not_empty",
+            FineError::UnknownCurrency(_) => "unknown_currency",
+            FineError::NotTransferable(_) => "currency_not_transferable",
+            FineError::InvalidAmount(_) => "invalid_amount",
+            FineError::InsufficientCredit => "insufficient_credit",
+            FineError::AmountOverflow => "amount_overflow",
+            FineError::SelfTransfer => "self_transfer",
+            FineError::HoldNotFound(_) => "hold_not_found",
+            FineError::TransactionNotFound(_) => "transaction_not_found",
+            FineError::AlreadyReversed(_) => "transaction_already_reversed",
+            FineError::NotReversible(_) => "transaction_not_reversible",
+            FineError::InvalidBatch(_) => "invalid_batch",
+            FineError::BatchRejected(_) => "batch_rejected",
+            FineError::InvalidSchedule(_) => "invalid_schedule",
+            FineError::ScheduledPaymentNotFound(_) => "scheduled_payment_not_found",
+            FineError::TooManySubscriptions => "too_many_subscriptions",
+            FineError::IdempotencyKeyReused(_) => "idempotency_key_reused",
+            FineError::TooManyConflicts => "too_many_conflicts",
+            FineError::Storage(_) | FineError::BrokenRecord(_) => "storage_error",
+            FineError::StorageUnavailable => "storage_unavailable",
+            FineError::OutcomeUnknown => "outcome_unknown",
+        }
+    }
+
+    // 连接层面的临时错误, 换一条连接重试可能成功
+    pub fn is_transient(&self) -> bool {
+        matches!(self, FineError::Storage(err) if storage::pool::is_transient(err))
+    }
+}
+
+impl fmt::Display for FineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FineError::Unauthorized => write!(f, "missing or invalid client token"),
+            FineError::Forbidden(reason) => write!(f, "forbidden: {}", reason),
+            FineError::InvalidMessage(err) => write!(f, "invalid message: {}", err),
+            FineError::UnknownMessageType => write!(f, "unknown message type"),
+            FineError::InvalidPayload(err) => write!(f, "invalid payload: {}", err),
+            FineError::UserNotFound(user_id) => write!(f, "user {} not found", user_id),
+            FineError::AccountExists(user_id) => write!(f, "account of {} already exists", user_id),
+            FineError::AccountFrozen(user_id) => write!(f, "account of {} is frozen", user_id),
+            FineError::AccountClosed(user_id) => write!(f, "account of {} is closed", user_id),
+            FineError::AccountNotEmpty(user_id) => {
+                write!(f, "account of {} still has credit", user_id)
+            }
+            FineError::UnknownCurrency(currency) => write!(f, "unknown currency {}", currency),
+            FineError::NotTransferable(currency) => {
+                write!(f, "currency {} is not transferable", currency)
+            }
+            FineError::InvalidAmount(reason) => write!(f, "invalid amount: {}", reason),
+            FineError::InsufficientCredit => write!(f, "credit not enough"),
+            FineError::AmountOverflow => write!(f, "amount overflow"),
+            FineError::SelfTransfer => write!(f, "cannot transfer to self"),
+            FineError::HoldNotFound(hold_id) => write!(f, "hold {} not found", hold_id),
+            FineError::TransactionNotFound(transaction_id) => {
+                write!(f, "transaction {} not found", transaction_id)
+            }
+            FineError::AlreadyReversed(transaction_id) => {
+                write!(f, "transaction {} is already reversed", transaction_id)
+            }
+            FineError::NotReversible(transaction_id) => {
+                write!(f, "transaction {} cannot be reversed", transaction_id)
+            }
+            FineError::InvalidBatch(reason) => write!(f, "invalid batch: {}", reason),
+            FineError::BatchRejected(_) => write!(f, "batch rejected, no entry was applied"),
+            FineError::InvalidSchedule(reason) => write!(f, "invalid schedule: {}", reason),
+            FineError::ScheduledPaymentNotFound(job_id) => {
+                write!(f, "scheduled payment {} not found", job_id)
+            }
+            FineError::TooManySubscriptions => write!(f, "too many subscribed users"),
+            FineError::IdempotencyKeyReused(key) => {
+                write!(f, "idempotency key {} was used by a different request", key)
+            }
+            FineError::TooManyConflicts => {
+                write!(f, "too many concurrent updates, please retry")
+            }
+            FineError::Storage(err) => write!(f, "storage error: {}", err),
+            FineError::StorageUnavailable => {
+                write!(f, "storage is temporarily unavailable, please retry")
+            }
+            FineError::OutcomeUnknown => write!(
+                f,
+                "storage failed while processing the request, it may have been applied; check before retrying"
+            ),
+            FineError::BrokenRecord(err) => write!(f, "broken record: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FineError {}
+
+impl From<RedisError> for FineError {
+    fn from(err: RedisError) -> Self {
+        FineError::Storage(err)
+    }
+}
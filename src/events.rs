@@ -0,0 +1,60 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use tokio::sync::broadcast;
+use tracing::{info, log::warn};
+
+use crate::model::ecosystem::EcosystemCreditChangedEvent;
+
+// 余额变动事件的 redis 发布频道
+pub const CREDIT_CHANGED_CHANNEL: &str = "ecosystem:events:credit_changed";
+
+// 与 redis 的订阅断开后重新订阅前的等待时间
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+// 一个连接订阅的余额变动事件
+#[derive(Default)]
+pub struct Subscription {
+    pub all: bool, // 订阅所有用户
+    pub user_ids: HashSet<String>,
+}
+
+impl Subscription {
+    pub fn matches(&self, user_id: &str) -> bool {
+        self.all || self.user_ids.contains(user_id)
+    }
+}
+
+// 从 redis 订阅余额变动事件, 转发给本实例上的所有连接
+pub async fn run_event_listener(
+    redis_client: redis::Client,
+    sender: broadcast::Sender<Arc<EcosystemCreditChangedEvent>>,
+) {
+    loop {
+        if let Err(err) = listen(&redis_client, &sender).await {
+            warn!("Credit change event subscription failed: {}", err);
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn listen(
+    redis_client: &redis::Client,
+    sender: &broadcast::Sender<Arc<EcosystemCreditChangedEvent>>,
+) -> Result<(), redis::RedisError> {
+    let mut pubsub = redis_client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(CREDIT_CHANGED_CHANNEL).await?;
+    info!("Subscribed to credit change events");
+    let mut messages = pubsub.on_message();
+    while let Some(msg) = messages.next().await {
+        let payload: String = msg.get_payload()?;
+        match serde_json::from_str(&payload) {
+            // 没有连接订阅时发送失败, 直接丢弃
+            Ok(event) => {
+                sender.send(Arc::new(event)).ok();
+            }
+            Err(err) => warn!("Dropped malformed credit change event: {}", err),
+        }
+    }
+    Ok(())
+}
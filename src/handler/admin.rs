@@ -0,0 +1,44 @@
+use crate::{
+    error::FineError,
+    message::{admin, Message, MessageType},
+    registry::ConnectionRegistry,
+};
+
+// 列出本实例上已连接的服务器
+pub fn list_connections(registry: &ConnectionRegistry) -> Result<Message, FineError> {
+    Ok(Message {
+        message_type: MessageType::AdminListConnectionsResponse,
+        request_id: None,
+        data: serde_json::to_value(admin::ListConnectionsResponseData {
+            connections: registry.list(),
+            recent_events: registry.recent_events(),
+        })
+        .unwrap(),
+    })
+}
+
+// 向本实例上指定名称的服务器发送通知
+pub fn send_to_server(
+    raw_data: serde_json::Value,
+    registry: &ConnectionRegistry,
+) -> Result<Message, FineError> {
+    let data: admin::SendToServerRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    let notice = Message {
+        message_type: MessageType::AdminServerNotice,
+        request_id: None,
+        data: data.data,
+    };
+    let (delivered, failed) = registry.send_to_server(&data.server_name, notice);
+    Ok(Message {
+        message_type: MessageType::AdminSendToServerResponse,
+        request_id: None,
+        data: serde_json::to_value(admin::SendToServerResponseData {
+            server_name: data.server_name,
+            delivered,
+            failed,
+        })
+        .unwrap(),
+    })
+}
@@ -1,196 +1,1304 @@
 use crate::{
+    auth::{ClientIdentity, Scope},
+    config::{CurrencyRule, EconomyConfig},
+    error::FineError,
     message::{
-        common::CommonErrorResponseData,
         ecosystem::{self, SetUserCreditResponseData},
         Message, MessageType,
     },
-    model::ecosystem::EcosystemUserAccountRecord,
+    model::ecosystem::{
+        EcosystemAccountStatus, EcosystemCreditChangeKind, EcosystemCreditHold,
+        EcosystemTransactionEntry, EcosystemUserAccountRecord, EcosystemUserCreditAlterRecord,
+    },
+    storage::{
+        self,
+        ecosystem::{AccountUpdate, Idempotency, IDEMPOTENCY_KEY_TTL_SECONDS},
+        store::AccountStore,
+    },
 };
-use redis::{aio::Connection, AsyncCommands, RedisError};
+use redis::aio::MultiplexedConnection;
+use std::collections::HashMap;
+use tracing::info;
+
+// 单页资产变动记录的最大条数
+const MAX_HISTORY_PAGE_SIZE: usize = 100;
+
+// 排行榜单次查询的最大条数
+const MAX_LEADERBOARD_TOP: usize = 100;
+
+// 批量增减余额单次的最大条目数
+const MAX_BATCH_SIZE: usize = 200;
+
+// 冻结款的最长有效期(7天)
+const MAX_HOLD_TTL_SECONDS: i64 = 7 * 24 * 3600;
+
+fn checked_add(credit: i64, amount: i64) -> Result<i64, FineError> {
+    credit.checked_add(amount).ok_or(FineError::AmountOverflow)
+}
+
+fn checked_sub(credit: i64, amount: i64) -> Result<i64, FineError> {
+    credit.checked_sub(amount).ok_or(FineError::AmountOverflow)
+}
+
+fn ensure_not_closed(account: &EcosystemUserAccountRecord, user_id: &str) -> Result<(), FineError> {
+    if account.status == EcosystemAccountStatus::Closed {
+        return Err(FineError::AccountClosed(user_id.to_string()));
+    }
+    Ok(())
+}
+
+// 冻结的账户不能转出或扣款
+fn ensure_not_frozen(account: &EcosystemUserAccountRecord, user_id: &str) -> Result<(), FineError> {
+    if account.status == EcosystemAccountStatus::Frozen {
+        return Err(FineError::AccountFrozen(user_id.to_string()));
+    }
+    Ok(())
+}
+
+// 本次操作产生的一条资产变动记录, 交易对方与自定义信息由调用方按需补充
+fn new_record(
+    time: i64,
+    credit: i64,
+    kind: EcosystemCreditChangeKind,
+    transaction_id: &str,
+    identity: &ClientIdentity,
+) -> EcosystemUserCreditAlterRecord {
+    EcosystemUserCreditAlterRecord {
+        time,
+        credit,
+        kind,
+        counterparty: None,
+        source_client: Some(identity.name.clone()),
+        metadata: HashMap::new(),
+        transaction_id: transaction_id.to_string(),
+        reverses: None,
+        previous_credit: None,
+    }
+}
+
+// 请求中的备注并入自定义信息的 reason 字段
+fn record_metadata(reason: &str, metadata: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut metadata = metadata.clone();
+    if !reason.is_empty() {
+        metadata.insert("reason".to_string(), reason.to_string());
+    }
+    metadata
+}
+
+// 交易id与冻结款id均为随机的128位十六进制串
+pub fn random_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+pub fn currency_rule<'a>(
+    config: &'a EconomyConfig,
+    currency: &str,
+) -> Result<&'a CurrencyRule, FineError> {
+    config
+        .currency(currency)
+        .ok_or_else(|| FineError::UnknownCurrency(currency.to_string()))
+}
 
 // 设置一个用户的余额
-pub async fn set_user_credit(raw_data: serde_json::Value, redis_conn: &mut Connection) -> Message {
-    let data: ecosystem::SetUserCreditRequestData = serde_json::from_value(raw_data).unwrap();
+pub async fn set_user_credit<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::SetUserCreditRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
 
-    let redis_key = format!("ecosystem:account:{}", data.user_id);
-    let mut user_record = EcosystemUserAccountRecord {
-        credit: 0,
-        alter_records: vec![],
-    };
-    let user_record_json: String = redis_conn.get(&redis_key).await.unwrap_or_default();
-    if user_record_json != "" {
-        user_record = serde_json::from_str(&user_record_json).unwrap();
-    }
-    user_record.credit = data.credit;
-    // TODO 封装redis的操作方法
-    user_record
-        .alter_records
-        .push(crate::model::ecosystem::EcosystemUserCreditAlterRecord {
-            time: chrono::Utc::now().timestamp(),
-            credit: data.credit,
-            reason: data.reason,
-        });
-    // TODO 完善错误处理
-    let r: Result<(), RedisError> = redis_conn
-        .set(&redis_key, serde_json::to_string(&user_record).unwrap())
-        .await;
-    if r.is_err() {
-        return Message::from(r.unwrap_err());
+    let rule = currency_rule(config, &data.currency)?;
+    if data.credit < 0 && !rule.allow_negative {
+        return Err(FineError::InvalidAmount(format!(
+            "{} cannot be negative",
+            data.currency
+        )));
     }
+    rule.limits.set.check(data.credit)?;
+
+    let idempotency = data
+        .idempotency_key
+        .as_deref()
+        .map(|key| Idempotency::new(identity, "set_user_credit", key, &raw_data));
+    let transaction_id = random_id();
+    let (credit, transaction_id) = store
+        .update_accounts(
+            &data.currency,
+            &[&data.user_id],
+            idempotency.as_ref(),
+            |accounts| {
+                let user_record = accounts[0]
+                    .account
+                    .get_or_insert_with(|| EcosystemUserAccountRecord::new(0));
+                ensure_not_closed(user_record, &data.user_id)?;
+                let previous_credit = user_record.credit;
+                user_record.credit = data.credit;
+                let credit = user_record.credit;
+                accounts[0]
+                    .new_records
+                    .push(EcosystemUserCreditAlterRecord {
+                        metadata: record_metadata(&data.reason, &data.metadata),
+                        previous_credit: Some(previous_credit),
+                        ..new_record(
+                            chrono::Utc::now().timestamp(),
+                            data.credit,
+                            EcosystemCreditChangeKind::AdminSet,
+                            &transaction_id,
+                            identity,
+                        )
+                    });
+                Ok((credit, transaction_id.clone()))
+            },
+        )
+        .await?;
     let response_data = SetUserCreditResponseData {
+        currency: data.currency,
         user_id: data.user_id,
-        credit: user_record.credit,
+        credit,
+        transaction_id,
     };
-    Message {
+    Ok(Message {
         message_type: MessageType::EcosytemSetUserCreditResponse,
+        request_id: None,
         data: serde_json::to_value(response_data).unwrap(),
+    })
+}
+
+// 为用户开户, 可以附带初始余额
+pub async fn create_account<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::CreateAccountRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
+
+    let rule = currency_rule(config, &data.currency)?;
+    // 带初始余额开户等同于设置余额
+    if data.credit != 0 {
+        if !identity.has_scope(Scope::EcoSet) {
+            return Err(FineError::Forbidden(format!(
+                "missing scope {}",
+                Scope::EcoSet.as_str()
+            )));
+        }
+        if data.credit < 0 && !rule.allow_negative {
+            return Err(FineError::InvalidAmount(format!(
+                "{} cannot be negative",
+                data.currency
+            )));
+        }
+        rule.limits.set.check(data.credit)?;
     }
+
+    let idempotency = data
+        .idempotency_key
+        .as_deref()
+        .map(|key| Idempotency::new(identity, "create_account", key, &raw_data));
+    let transaction_id = random_id();
+    let transaction_id = store
+        .update_accounts(
+            &data.currency,
+            &[&data.user_id],
+            idempotency.as_ref(),
+            |accounts| {
+                if accounts[0].account.is_some() {
+                    return Err(FineError::AccountExists(data.user_id.clone()));
+                }
+                accounts[0].account = Some(EcosystemUserAccountRecord::new(data.credit));
+                if data.credit != 0 {
+                    accounts[0]
+                        .new_records
+                        .push(EcosystemUserCreditAlterRecord {
+                            metadata: record_metadata(&data.reason, &data.metadata),
+                            previous_credit: Some(0),
+                            ..new_record(
+                                chrono::Utc::now().timestamp(),
+                                data.credit,
+                                EcosystemCreditChangeKind::AdminSet,
+                                &transaction_id,
+                                identity,
+                            )
+                        });
+                    return Ok(Some(transaction_id.clone()));
+                }
+                Ok(None)
+            },
+        )
+        .await?;
+    Ok(Message {
+        message_type: MessageType::EcosytemCreateAccountResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::CreateAccountResponseData {
+            currency: data.currency,
+            user_id: data.user_id,
+            credit: data.credit,
+            transaction_id,
+        })
+        .unwrap(),
+    })
+}
+
+// 冻结账户
+pub async fn freeze_account<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    change_account_status(
+        raw_data,
+        store,
+        config,
+        EcosystemAccountStatus::Frozen,
+        MessageType::EcosytemFreezeAccountResponse,
+    )
+    .await
+}
+
+// 解冻账户
+pub async fn unfreeze_account<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    change_account_status(
+        raw_data,
+        store,
+        config,
+        EcosystemAccountStatus::Active,
+        MessageType::EcosytemUnfreezeAccountResponse,
+    )
+    .await
+}
+
+// 注销账户, 余额必须为0
+pub async fn close_account<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    change_account_status(
+        raw_data,
+        store,
+        config,
+        EcosystemAccountStatus::Closed,
+        MessageType::EcosytemCloseAccountResponse,
+    )
+    .await
+}
+
+async fn change_account_status<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    status: EcosystemAccountStatus,
+    message_type: MessageType,
+) -> Result<Message, FineError> {
+    let data: ecosystem::AccountStatusRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    currency_rule(config, &data.currency)?;
+    store
+        .update_accounts(&data.currency, &[&data.user_id], None, |accounts| {
+            let user_account = accounts[0]
+                .account
+                .as_mut()
+                .ok_or_else(|| FineError::UserNotFound(data.user_id.clone()))?;
+            ensure_not_closed(user_account, &data.user_id)?;
+            if status == EcosystemAccountStatus::Closed {
+                let now = chrono::Utc::now().timestamp();
+                if user_account.credit != 0 || user_account.held_credit(now) != 0 {
+                    return Err(FineError::AccountNotEmpty(data.user_id.clone()));
+                }
+                user_account.prune_expired_holds(now);
+            }
+            user_account.status = status;
+            Ok(())
+        })
+        .await?;
+    info!(
+        "Account {} of {} is now {:?}: {}",
+        data.currency, data.user_id, status, data.reason
+    );
+    Ok(Message {
+        message_type,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::AccountStatusResponseData {
+            currency: data.currency,
+            user_id: data.user_id,
+            status,
+        })
+        .unwrap(),
+    })
 }
 
 // 获取一个用户的余额
-pub async fn get_user_credit(raw_data: serde_json::Value, redis_conn: &mut Connection) -> Message {
-    let data: ecosystem::GetUserCreditRequestData = serde_json::from_value(raw_data).unwrap();
-
-    let redis_key = format!("ecosystem:account:{}", data.user_id);
-    let user_account_record: Option<String> = redis_conn.get(&redis_key).await.unwrap();
-    if user_account_record.is_none() {
-        return Message::from(CommonErrorResponseData {
-            message: "user not found".to_string(),
-        });
-    }
-    let user_account: EcosystemUserAccountRecord =
-        serde_json::from_str(user_account_record.unwrap().as_str()).unwrap();
+pub async fn get_user_credit<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    let data: ecosystem::GetUserCreditRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    currency_rule(config, &data.currency)?;
+    let user_account = store
+        .get_account(&data.currency, &data.user_id)
+        .await?
+        .ok_or_else(|| FineError::UserNotFound(data.user_id.clone()))?;
+    let now = chrono::Utc::now().timestamp();
     let resp_data = ecosystem::GetUserCreditResponseData {
+        currency: data.currency,
         user_id: data.user_id,
         credit: user_account.credit,
-        alter_records: user_account.alter_records,
+        held: user_account.held_credit(now),
+        available: user_account.available_credit(now),
+        status: user_account.status,
     };
-    Message {
+    Ok(Message {
         message_type: MessageType::EcosytemGetUserCreditResponse,
+        request_id: None,
         data: serde_json::to_value(resp_data).unwrap(),
+    })
+}
+
+// 分页查询用户的资产变动记录
+pub async fn get_user_credit_history(
+    raw_data: serde_json::Value,
+    redis_conn: &mut MultiplexedConnection,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    let data: ecosystem::GetUserCreditHistoryRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    currency_rule(config, &data.currency)?;
+    if storage::ecosystem::get_account(redis_conn, &data.currency, &data.user_id)
+        .await?
+        .is_none()
+    {
+        return Err(FineError::UserNotFound(data.user_id));
     }
+    let (alter_records, next_cursor) = storage::ecosystem::get_history_page(
+        redis_conn,
+        &data.currency,
+        &data.user_id,
+        data.cursor,
+        data.limit.clamp(1, MAX_HISTORY_PAGE_SIZE),
+        &storage::ecosystem::HistoryFilter {
+            since: data.since,
+            until: data.until,
+            kind: data.kind,
+            counterparty: data.counterparty.as_deref(),
+            reason_prefix: data.reason_prefix.as_deref(),
+        },
+    )
+    .await?;
+    Ok(Message {
+        message_type: MessageType::EcosytemGetUserCreditHistoryResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::GetUserCreditHistoryResponseData {
+            currency: data.currency,
+            user_id: data.user_id,
+            alter_records,
+            next_cursor,
+        })
+        .unwrap(),
+    })
 }
 
 // 增减用户余额
-pub async fn alter_user_credit(
-    raw_data: serde_json::Value,
-    redis_conn: &mut Connection,
-) -> Message {
-    let data: ecosystem::AlterUserCreditRequestData = serde_json::from_value(raw_data).unwrap();
-
-    let redis_key = format!("ecosystem:account:{}", data.user_id);
-    let user_account_record: Option<String> = redis_conn.get(&redis_key).await.unwrap();
-    if user_account_record.is_none() {
-        return Message::from(CommonErrorResponseData {
-            message: "user not found".to_string(),
-        });
-    }
-    let mut user_account: EcosystemUserAccountRecord =
-        serde_json::from_str(user_account_record.unwrap().as_str()).unwrap();
-    user_account.credit += data.credit;
-    user_account
-        .alter_records
-        .push(crate::model::ecosystem::EcosystemUserCreditAlterRecord {
-            time: chrono::Utc::now().timestamp(),
-            credit: data.credit,
-            reason: data.reason,
-        });
-    if user_account.credit < 0 {
-        return Message::from(CommonErrorResponseData {
-            message: "credit not enough".to_string(),
-        });
-    }
-    let r: Result<(), RedisError> = redis_conn
-        .set(&redis_key, serde_json::to_string(&user_account).unwrap())
-        .await;
-    if r.is_err() {
-        return Message::from(r.unwrap_err());
-    }
-    Message {
+pub async fn alter_user_credit<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    alter_user_credit_with_ttl(
+        raw_data,
+        store,
+        config,
+        identity,
+        IDEMPOTENCY_KEY_TTL_SECONDS,
+    )
+    .await
+}
+
+// 同 alter_user_credit, 幂等记录保留 idempotency_ttl_seconds 秒
+pub async fn alter_user_credit_with_ttl<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+    idempotency_ttl_seconds: usize,
+) -> Result<Message, FineError> {
+    let data: ecosystem::AlterUserCreditRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
+
+    let rule = currency_rule(config, &data.currency)?;
+    check_alter_amount(rule, identity, data.credit)?;
+
+    let idempotency = data.idempotency_key.as_deref().map(|key| {
+        Idempotency::new(identity, "alter_user_credit", key, &raw_data)
+            .with_ttl(idempotency_ttl_seconds)
+    });
+    let transaction_id = random_id();
+    let (credit, transaction_id) = store
+        .update_accounts(
+            &data.currency,
+            &[&data.user_id],
+            idempotency.as_ref(),
+            |accounts| {
+                let now = chrono::Utc::now().timestamp();
+                let credit = apply_alter(&mut accounts[0], &data.user_id, data.credit, rule, now)?;
+                accounts[0]
+                    .new_records
+                    .push(EcosystemUserCreditAlterRecord {
+                        metadata: record_metadata(&data.reason, &data.metadata),
+                        ..new_record(
+                            now,
+                            data.credit,
+                            data.kind.into(),
+                            &transaction_id,
+                            identity,
+                        )
+                    });
+                Ok((credit, transaction_id.clone()))
+            },
+        )
+        .await?;
+    Ok(Message {
         message_type: MessageType::EcosytemAlterUserCreditResponse,
+        request_id: None,
         data: serde_json::to_value(ecosystem::AlterUserCreditResponseData {
+            currency: data.currency,
             user_id: data.user_id,
-            credit: user_account.credit,
+            credit,
+            transaction_id,
         })
         .unwrap(),
+    })
+}
+
+// 单笔增减的金额需要同时满足货币规则与客户端限额
+pub fn check_alter_amount(
+    rule: &CurrencyRule,
+    identity: &ClientIdentity,
+    credit: i64,
+) -> Result<(), FineError> {
+    rule.limits
+        .alter
+        .check(credit.checked_abs().ok_or(FineError::AmountOverflow)?)?;
+    if let Some(limit) = identity.alter_limit {
+        if credit.unsigned_abs() > limit.unsigned_abs() {
+            return Err(FineError::Forbidden(format!(
+                "alter amount exceeds client limit {}",
+                limit
+            )));
+        }
     }
+    Ok(())
 }
 
-// 用户对用户转账
-pub async fn transfer_user_credit(
-    raw_data: serde_json::Value,
-    redis_conn: &mut Connection,
-) -> Message {
-    let req: ecosystem::TransferCreditRequestData = serde_json::from_value(raw_data).unwrap();
-
-    let from_account_redis_key = format!("ecosystem:account:{}", req.from_user_id);
-    let to_account_redis_key = format!("ecosystem:account:{}", req.to_user_id);
-
-    let from_account_record: Option<String> =
-        redis_conn.get(&from_account_redis_key).await.unwrap();
-    if from_account_record.is_none() {
-        return Message::from(CommonErrorResponseData {
-            message: "from user not found".to_string(),
-        });
-    }
-    let to_account_record: Option<String> = redis_conn.get(&to_account_redis_key).await.unwrap();
-    if to_account_record.is_none() {
-        return Message::from(CommonErrorResponseData {
-            message: "to user not found".to_string(),
-        });
-    }
-    let mut from_account: EcosystemUserAccountRecord =
-        serde_json::from_str(from_account_record.unwrap().as_str()).unwrap();
-    if from_account.credit < req.credit {
-        return Message::from(CommonErrorResponseData {
-            message: "credit not enough".to_string(),
-        });
-    }
-    let mut to_account: EcosystemUserAccountRecord =
-        serde_json::from_str(to_account_record.unwrap().as_str()).unwrap();
-
-    from_account.credit -= req.credit;
-    from_account
-        .alter_records
-        .push(crate::model::ecosystem::EcosystemUserCreditAlterRecord {
-            time: chrono::Utc::now().timestamp(),
-            credit: -req.credit,
-            reason: format!(
-                "transfer$#$from:{}$#$to:{}",
-                req.from_user_id, req.to_user_id
-            ),
-        });
-    to_account.credit += req.credit;
-    to_account
-        .alter_records
-        .push(crate::model::ecosystem::EcosystemUserCreditAlterRecord {
-            time: chrono::Utc::now().timestamp(),
-            credit: req.credit,
-            reason: format!("transfer/from:{}/to:{}", req.from_user_id, req.to_user_id),
-        });
-    // TODO 引入transaction
-    let _: () = redis_conn
-        .set(
-            &from_account_redis_key,
-            serde_json::to_string(&from_account).unwrap(),
+// 对一个账户增减余额, 返回变动后的余额; 失败时账户余额保持不变
+fn apply_alter(
+    update: &mut AccountUpdate,
+    user_id: &str,
+    credit: i64,
+    rule: &CurrencyRule,
+    now: i64,
+) -> Result<i64, FineError> {
+    let user_account = update
+        .account
+        .as_mut()
+        .ok_or_else(|| FineError::UserNotFound(user_id.to_string()))?;
+    ensure_not_closed(user_account, user_id)?;
+    if credit < 0 {
+        ensure_not_frozen(user_account, user_id)?;
+    }
+    user_account.prune_expired_holds(now);
+    let new_credit = checked_add(user_account.credit, credit)?;
+    // 扣款不能动用冻结款
+    let available = checked_sub(new_credit, user_account.held_credit(now))?;
+    if (new_credit < 0 || (credit < 0 && available < 0)) && !rule.allow_negative {
+        return Err(FineError::InsufficientCredit);
+    }
+    user_account.credit = new_credit;
+    Ok(new_credit)
+}
+
+// 批量增减余额, 所有生效的项共用一个交易id
+// all_or_nothing 模式下任一项失败则全部不生效; best_effort 模式下跳过失败的项
+pub async fn batch_alter_user_credit<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::BatchAlterRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
+
+    let rule = currency_rule(config, &data.currency)?;
+    if data.entries.is_empty() || data.entries.len() > MAX_BATCH_SIZE {
+        return Err(FineError::InvalidBatch(format!(
+            "batch must contain 1 to {} entries",
+            MAX_BATCH_SIZE
+        )));
+    }
+    let mut user_ids: Vec<&str> = vec![];
+    for entry in data.entries.iter() {
+        if !user_ids.contains(&entry.user_id.as_str()) {
+            user_ids.push(&entry.user_id);
+        }
+    }
+
+    let idempotency = data
+        .idempotency_key
+        .as_deref()
+        .map(|key| Idempotency::new(identity, "batch_alter_user_credit", key, &raw_data));
+    let transaction_id = random_id();
+    let updated = store
+        .update_accounts(
+            &data.currency,
+            &user_ids,
+            idempotency.as_ref(),
+            |accounts| {
+                let now = chrono::Utc::now().timestamp();
+                let mut results = Vec::with_capacity(data.entries.len());
+                for entry in data.entries.iter() {
+                    let index = user_ids
+                        .iter()
+                        .position(|user_id| *user_id == entry.user_id)
+                        .unwrap();
+                    let applied = check_alter_amount(rule, identity, entry.credit).and_then(|_| {
+                        apply_alter(
+                            &mut accounts[index],
+                            &entry.user_id,
+                            entry.credit,
+                            rule,
+                            now,
+                        )
+                    });
+                    results.push(match applied {
+                        Ok(credit) => {
+                            accounts[index]
+                                .new_records
+                                .push(EcosystemUserCreditAlterRecord {
+                                    metadata: record_metadata(&entry.reason, &entry.metadata),
+                                    ..new_record(
+                                        now,
+                                        entry.credit,
+                                        entry.kind.into(),
+                                        &transaction_id,
+                                        identity,
+                                    )
+                                });
+                            ecosystem::BatchEntryResult {
+                                user_id: entry.user_id.clone(),
+                                status: ecosystem::BatchEntryStatus::Applied,
+                                credit: Some(credit),
+                                error: None,
+                            }
+                        }
+                        Err(err) => ecosystem::BatchEntryResult {
+                            user_id: entry.user_id.clone(),
+                            status: ecosystem::BatchEntryStatus::Failed,
+                            credit: None,
+                            error: Some(err.into()),
+                        },
+                    });
+                }
+                let failed = results
+                    .iter()
+                    .any(|result| result.status == ecosystem::BatchEntryStatus::Failed);
+                if failed && data.mode == ecosystem::BatchMode::AllOrNothing {
+                    // 返回错误使本次修改不写入, 也不保存幂等结果
+                    for result in results.iter_mut() {
+                        if result.status == ecosystem::BatchEntryStatus::Applied {
+                            result.status = ecosystem::BatchEntryStatus::Skipped;
+                            result.credit = None;
+                        }
+                    }
+                    return Err(FineError::BatchRejected(results));
+                }
+                let any_applied = results
+                    .iter()
+                    .any(|result| result.status == ecosystem::BatchEntryStatus::Applied);
+                Ok((results, any_applied.then(|| transaction_id.clone())))
+            },
+        )
+        .await;
+    // 整批未生效时仍返回各项的结果
+    let (results, transaction_id) = match updated {
+        Err(FineError::BatchRejected(results)) => (results, None),
+        updated => updated?,
+    };
+    Ok(Message {
+        message_type: MessageType::EcosytemBatchAlterResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::BatchAlterResponseData {
+            currency: data.currency,
+            mode: data.mode,
+            transaction_id,
+            results,
+        })
+        .unwrap(),
+    })
+}
+
+// 检查转账请求是否符合货币规则
+pub fn check_transfer(
+    rule: &CurrencyRule,
+    req: &ecosystem::TransferCreditRequestData,
+) -> Result<(), FineError> {
+    if !rule.transferable {
+        return Err(FineError::NotTransferable(req.currency.clone()));
+    }
+    // 负数金额会让转账反向进行, 从收款方扣款
+    if req.credit <= 0 {
+        return Err(FineError::InvalidAmount(
+            "transfer amount must be positive".to_string(),
+        ));
+    }
+    rule.limits.transfer.check(req.credit)?;
+    // 两端为同一账户时后写入的一方会覆盖前者, 凭空产生余额
+    if req.from_user_id == req.to_user_id {
+        return Err(FineError::SelfTransfer);
+    }
+    Ok(())
+}
+
+// 一笔转账应收的手续费
+struct TransferFee<'a> {
+    system_account: &'a str,
+    amount: i64,
+}
+
+// 计算转账的手续费, 不收取时返回None; 任一方为系统账户时不收取手续费
+fn transfer_fee<'a>(
+    rule: &'a CurrencyRule,
+    from_user_id: &str,
+    to_user_id: &str,
+    amount: i64,
+) -> Result<Option<TransferFee<'a>>, FineError> {
+    let Some(fee_config) = &rule.transfer_fee else {
+        return Ok(None);
+    };
+    if fee_config.system_account == from_user_id || fee_config.system_account == to_user_id {
+        return Ok(None);
+    }
+    let amount = fee_config.fee_for(amount)?;
+    Ok((amount > 0).then_some(TransferFee {
+        system_account: &fee_config.system_account,
+        amount,
+    }))
+}
+
+// 将手续费记入系统账户, 并为双方写入手续费记录; 转出方的余额由调用方扣除
+fn collect_fee(
+    from: &mut AccountUpdate,
+    from_user_id: &str,
+    system: &mut AccountUpdate,
+    fee: &TransferFee,
+    now: i64,
+    transaction_id: &str,
+    identity: &ClientIdentity,
+) -> Result<(), FineError> {
+    let system_record = system
+        .account
+        .get_or_insert_with(|| EcosystemUserAccountRecord::new(0));
+    ensure_not_closed(system_record, fee.system_account)?;
+    system_record.credit = checked_add(system_record.credit, fee.amount)?;
+    system.new_records.push(EcosystemUserCreditAlterRecord {
+        counterparty: Some(from_user_id.to_string()),
+        ..new_record(
+            now,
+            fee.amount,
+            EcosystemCreditChangeKind::Fee,
+            transaction_id,
+            identity,
         )
-        .await
-        .unwrap();
-    let _: () = redis_conn
-        .set(
-            &to_account_redis_key,
-            serde_json::to_string(&to_account).unwrap(),
+    });
+    from.new_records.push(EcosystemUserCreditAlterRecord {
+        counterparty: Some(fee.system_account.to_string()),
+        ..new_record(
+            now,
+            -fee.amount,
+            EcosystemCreditChangeKind::Fee,
+            transaction_id,
+            identity,
         )
-        .await
-        .unwrap();
-    Message {
+    });
+    Ok(())
+}
+
+// 用户对用户转账
+pub async fn transfer_user_credit<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    transfer_user_credit_with_ttl(
+        raw_data,
+        store,
+        config,
+        identity,
+        IDEMPOTENCY_KEY_TTL_SECONDS,
+    )
+    .await
+}
+
+// 同 transfer_user_credit, 幂等记录保留 idempotency_ttl_seconds 秒
+pub async fn transfer_user_credit_with_ttl<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+    idempotency_ttl_seconds: usize,
+) -> Result<Message, FineError> {
+    let req: ecosystem::TransferCreditRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
+
+    let rule = currency_rule(config, &req.currency)?;
+    check_transfer(rule, &req)?;
+    let fee = transfer_fee(rule, &req.from_user_id, &req.to_user_id, req.credit)?;
+    let mut user_ids = vec![req.from_user_id.as_str(), req.to_user_id.as_str()];
+    user_ids.extend(fee.as_ref().map(|fee| fee.system_account));
+    let idempotency = req.idempotency_key.as_deref().map(|key| {
+        Idempotency::new(identity, "transfer_user_credit", key, &raw_data)
+            .with_ttl(idempotency_ttl_seconds)
+    });
+    let transaction_id = random_id();
+    let (from_user_credit, to_user_credit, transaction_id) = store
+        .update_accounts(&req.currency, &user_ids, idempotency.as_ref(), |accounts| {
+            let (from, rest) = accounts.split_at_mut(1);
+            let (to, system) = rest.split_at_mut(1);
+            let (from, to) = (&mut from[0], &mut to[0]);
+            let from_account = from
+                .account
+                .as_mut()
+                .ok_or_else(|| FineError::UserNotFound(req.from_user_id.clone()))?;
+            let to_account = to
+                .account
+                .as_mut()
+                .ok_or_else(|| FineError::UserNotFound(req.to_user_id.clone()))?;
+            ensure_not_closed(from_account, &req.from_user_id)?;
+            ensure_not_frozen(from_account, &req.from_user_id)?;
+            ensure_not_closed(to_account, &req.to_user_id)?;
+            let now = chrono::Utc::now().timestamp();
+            from_account.prune_expired_holds(now);
+            let fee_amount = fee.as_ref().map_or(0, |fee| fee.amount);
+            let from_credit =
+                checked_sub(checked_sub(from_account.credit, req.credit)?, fee_amount)?;
+            // 冻结款不能用于转账
+            let from_available = checked_sub(from_credit, from_account.held_credit(now))?;
+            if from_available < 0 && !rule.allow_negative {
+                return Err(FineError::InsufficientCredit);
+            }
+            let to_credit = checked_add(to_account.credit, req.credit)?;
+
+            from_account.credit = from_credit;
+            from.new_records.push(EcosystemUserCreditAlterRecord {
+                counterparty: Some(req.to_user_id.clone()),
+                metadata: req.metadata.clone(),
+                ..new_record(
+                    now,
+                    -req.credit,
+                    EcosystemCreditChangeKind::TransferOut,
+                    &transaction_id,
+                    identity,
+                )
+            });
+            to_account.credit = to_credit;
+            to.new_records.push(EcosystemUserCreditAlterRecord {
+                counterparty: Some(req.from_user_id.clone()),
+                metadata: req.metadata.clone(),
+                ..new_record(
+                    now,
+                    req.credit,
+                    EcosystemCreditChangeKind::TransferIn,
+                    &transaction_id,
+                    identity,
+                )
+            });
+            if let (Some(fee), Some(system)) = (&fee, system.first_mut()) {
+                collect_fee(
+                    from,
+                    &req.from_user_id,
+                    system,
+                    fee,
+                    now,
+                    &transaction_id,
+                    identity,
+                )?;
+            }
+            Ok((from_credit, to_credit, transaction_id.clone()))
+        })
+        .await?;
+    Ok(Message {
         message_type: MessageType::EcosytemTransferUserCreditResponse,
+        request_id: None,
         data: serde_json::to_value(ecosystem::TransferCreditResponseData {
+            currency: req.currency,
             from_user_id: req.from_user_id,
-            from_user_credit: from_account.credit,
+            from_user_credit,
             to_user_id: req.to_user_id,
-            to_user_credit: to_account.credit,
+            to_user_credit,
+            fee: fee.map_or(0, |fee| fee.amount),
+            transaction_id,
         })
         .unwrap(),
+    })
+}
+
+// 冻结用户的部分余额, 冻结款计入总余额但不能被使用
+pub async fn hold_credit<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::HoldCreditRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
+
+    let rule = currency_rule(config, &data.currency)?;
+    if data.credit <= 0 {
+        return Err(FineError::InvalidAmount(
+            "hold amount must be positive".to_string(),
+        ));
     }
+    // 冻结款最终会以转账结算, 提前拒绝结算时必然超出限额的冻结
+    rule.limits.transfer.check(data.credit)?;
+    if data.ttl_seconds <= 0 || data.ttl_seconds > MAX_HOLD_TTL_SECONDS {
+        return Err(FineError::InvalidAmount(format!(
+            "hold ttl must be between 1 and {} seconds",
+            MAX_HOLD_TTL_SECONDS
+        )));
+    }
+
+    let idempotency = data
+        .idempotency_key
+        .as_deref()
+        .map(|key| Idempotency::new(identity, "hold_credit", key, &raw_data));
+    let hold_id = random_id();
+    let (hold, credit, held, available) = store
+        .update_accounts(
+            &data.currency,
+            &[&data.user_id],
+            idempotency.as_ref(),
+            |accounts| {
+                let user_account = accounts[0]
+                    .account
+                    .as_mut()
+                    .ok_or_else(|| FineError::UserNotFound(data.user_id.clone()))?;
+                ensure_not_closed(user_account, &data.user_id)?;
+                ensure_not_frozen(user_account, &data.user_id)?;
+                let now = chrono::Utc::now().timestamp();
+                user_account.prune_expired_holds(now);
+                if user_account.available_credit(now) < data.credit && !rule.allow_negative {
+                    return Err(FineError::InsufficientCredit);
+                }
+                let hold = EcosystemCreditHold {
+                    hold_id: hold_id.clone(),
+                    credit: data.credit,
+                    created_at: now,
+                    expires_at: now + data.ttl_seconds,
+                    reason: data.reason.clone(),
+                };
+                user_account.holds.push(hold.clone());
+                Ok((
+                    hold,
+                    user_account.credit,
+                    user_account.held_credit(now),
+                    user_account.available_credit(now),
+                ))
+            },
+        )
+        .await?;
+    Ok(Message {
+        message_type: MessageType::EcosytemHoldCreditResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::HoldCreditResponseData {
+            currency: data.currency,
+            user_id: data.user_id,
+            hold_id: hold.hold_id,
+            expires_at: hold.expires_at,
+            credit,
+            held,
+            available,
+        })
+        .unwrap(),
+    })
 }
+
+// 将一笔冻结款结算给收款方, 相当于一次转账: 同样受转账限额约束,
+// 手续费在冻结款之外从付款方的可用余额中扣除
+pub async fn capture_hold<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let req: ecosystem::CaptureHoldRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
+
+    let rule = currency_rule(config, &req.currency)?;
+    if !rule.transferable {
+        return Err(FineError::NotTransferable(req.currency));
+    }
+    if req.user_id == req.to_user_id {
+        return Err(FineError::SelfTransfer);
+    }
+    let mut user_ids = vec![req.user_id.as_str(), req.to_user_id.as_str()];
+    if let Some(fee_config) = &rule.transfer_fee {
+        // 手续费取决于冻结款的金额, 读取账户前无法确定, 先把系统账户一起读出
+        if !user_ids.contains(&fee_config.system_account.as_str()) {
+            user_ids.push(&fee_config.system_account);
+        }
+    }
+
+    let idempotency = req
+        .idempotency_key
+        .as_deref()
+        .map(|key| Idempotency::new(identity, "capture_hold", key, &raw_data));
+    let transaction_id = random_id();
+    let (user_credit, to_user_credit, captured, fee, transaction_id) = store
+        .update_accounts(&req.currency, &user_ids, idempotency.as_ref(), |accounts| {
+            let (from, rest) = accounts.split_at_mut(1);
+            let (to, system) = rest.split_at_mut(1);
+            let (from, to) = (&mut from[0], &mut to[0]);
+            let from_account = from
+                .account
+                .as_mut()
+                .ok_or_else(|| FineError::UserNotFound(req.user_id.clone()))?;
+            let to_account = to
+                .account
+                .as_mut()
+                .ok_or_else(|| FineError::UserNotFound(req.to_user_id.clone()))?;
+            ensure_not_closed(from_account, &req.user_id)?;
+            ensure_not_frozen(from_account, &req.user_id)?;
+            ensure_not_closed(to_account, &req.to_user_id)?;
+
+            let now = chrono::Utc::now().timestamp();
+            from_account.prune_expired_holds(now);
+            let index = from_account
+                .holds
+                .iter()
+                .position(|hold| hold.hold_id == req.hold_id)
+                .ok_or_else(|| FineError::HoldNotFound(req.hold_id.clone()))?;
+            let hold = from_account.holds.remove(index);
+            // 限额可能在冻结之后调整过, 以结算时的配置为准
+            rule.limits.transfer.check(hold.credit)?;
+            let fee = transfer_fee(rule, &req.user_id, &req.to_user_id, hold.credit)?;
+            let fee_amount = fee.as_ref().map_or(0, |fee| fee.amount);
+            let from_credit =
+                checked_sub(checked_sub(from_account.credit, hold.credit)?, fee_amount)?;
+            if !rule.allow_negative {
+                // 冻结后余额可能被直接设置得更低, 结算不能使余额为负
+                if from_credit < 0 {
+                    return Err(FineError::InsufficientCredit);
+                }
+                // 冻结款本身已预留, 手续费只能占用其余冻结款之外的可用余额
+                if fee_amount > 0 && checked_sub(from_credit, from_account.held_credit(now))? < 0 {
+                    return Err(FineError::InsufficientCredit);
+                }
+            }
+            let to_credit = checked_add(to_account.credit, hold.credit)?;
+
+            from_account.credit = from_credit;
+            let metadata = HashMap::from([("hold_id".to_string(), hold.hold_id.clone())]);
+            from.new_records.push(EcosystemUserCreditAlterRecord {
+                counterparty: Some(req.to_user_id.clone()),
+                metadata: metadata.clone(),
+                ..new_record(
+                    now,
+                    -hold.credit,
+                    EcosystemCreditChangeKind::TransferOut,
+                    &transaction_id,
+                    identity,
+                )
+            });
+            to_account.credit = to_credit;
+            to.new_records.push(EcosystemUserCreditAlterRecord {
+                counterparty: Some(req.user_id.clone()),
+                metadata,
+                ..new_record(
+                    now,
+                    hold.credit,
+                    EcosystemCreditChangeKind::TransferIn,
+                    &transaction_id,
+                    identity,
+                )
+            });
+            if let (Some(fee), Some(system)) = (&fee, system.first_mut()) {
+                collect_fee(
+                    from,
+                    &req.user_id,
+                    system,
+                    fee,
+                    now,
+                    &transaction_id,
+                    identity,
+                )?;
+            }
+            Ok((
+                from_credit,
+                to_credit,
+                hold.credit,
+                fee_amount,
+                transaction_id.clone(),
+            ))
+        })
+        .await?;
+    Ok(Message {
+        message_type: MessageType::EcosytemCaptureHoldResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::CaptureHoldResponseData {
+            currency: req.currency,
+            user_id: req.user_id,
+            user_credit,
+            to_user_id: req.to_user_id,
+            to_user_credit,
+            captured,
+            fee,
+            transaction_id,
+        })
+        .unwrap(),
+    })
+}
+
+// 释放一笔冻结款, 资金回到可用余额
+pub async fn release_hold<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    let data: ecosystem::ReleaseHoldRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    currency_rule(config, &data.currency)?;
+    let (released, available) = store
+        .update_accounts(&data.currency, &[&data.user_id], None, |accounts| {
+            let user_account = accounts[0]
+                .account
+                .as_mut()
+                .ok_or_else(|| FineError::UserNotFound(data.user_id.clone()))?;
+            let now = chrono::Utc::now().timestamp();
+            user_account.prune_expired_holds(now);
+            let index = user_account
+                .holds
+                .iter()
+                .position(|hold| hold.hold_id == data.hold_id)
+                .ok_or_else(|| FineError::HoldNotFound(data.hold_id.clone()))?;
+            let hold = user_account.holds.remove(index);
+            Ok((hold.credit, user_account.available_credit(now)))
+        })
+        .await?;
+    Ok(Message {
+        message_type: MessageType::EcosytemReleaseHoldResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::ReleaseHoldResponseData {
+            currency: data.currency,
+            user_id: data.user_id,
+            hold_id: data.hold_id,
+            released,
+            available,
+        })
+        .unwrap(),
+    })
+}
+
+// 冲正一笔交易: 为原交易的每条资产变动写入一条反向记录, 并与原交易互相关联
+// 一笔交易只能被冲正一次
+pub async fn reverse_transaction<S: AccountStore>(
+    raw_data: serde_json::Value,
+    store: &mut S,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::ReverseTransactionRequestData =
+        serde_json::from_value(raw_data.clone()).map_err(FineError::InvalidPayload)?;
+
+    let original = store
+        .get_transaction(&data.transaction_id)
+        .await?
+        .ok_or_else(|| FineError::TransactionNotFound(data.transaction_id.clone()))?;
+    let rule = currency_rule(config, &original.currency)?;
+    let mut user_ids: Vec<&str> = vec![];
+    for entry in original.entries.iter() {
+        if !user_ids.contains(&entry.user_id.as_str()) {
+            user_ids.push(&entry.user_id);
+        }
+    }
+
+    let idempotency = data
+        .idempotency_key
+        .as_deref()
+        .map(|key| Idempotency::new(identity, "reverse_transaction", key, &raw_data));
+    let transaction_id = random_id();
+    let (transaction_id, entries) = store
+        .update_accounts_linked(
+            &original.currency,
+            &user_ids,
+            Some(&data.transaction_id),
+            idempotency.as_ref(),
+            |accounts, linked| {
+                let linked = linked
+                    .ok_or_else(|| FineError::TransactionNotFound(data.transaction_id.clone()))?;
+                if linked.reversed_by.is_some() {
+                    return Err(FineError::AlreadyReversed(data.transaction_id.clone()));
+                }
+                let now = chrono::Utc::now().timestamp();
+                let metadata = record_metadata(&data.reason, &HashMap::new());
+                let mut deltas: HashMap<&str, i64> = HashMap::new();
+                let mut entries = vec![];
+                for entry in linked.entries.iter() {
+                    let index = user_ids
+                        .iter()
+                        .position(|user_id| *user_id == entry.user_id)
+                        .unwrap();
+                    let account = accounts[index]
+                        .account
+                        .as_mut()
+                        .ok_or_else(|| FineError::UserNotFound(entry.user_id.clone()))?;
+                    ensure_not_closed(account, &entry.user_id)?;
+                    // 设置余额的记录中是设置后的余额, 需要换算成实际的变动金额
+                    let change = match entry.kind {
+                        EcosystemCreditChangeKind::AdminSet => {
+                            let previous = entry.previous_credit.ok_or_else(|| {
+                                FineError::NotReversible(data.transaction_id.clone())
+                            })?;
+                            checked_sub(entry.credit, previous)?
+                        }
+                        _ => entry.credit,
+                    };
+                    account.credit = checked_sub(account.credit, change)?;
+                    let delta = deltas.entry(user_ids[index]).or_default();
+                    *delta = checked_sub(*delta, change)?;
+                    accounts[index]
+                        .new_records
+                        .push(EcosystemUserCreditAlterRecord {
+                            counterparty: entry.counterparty.clone(),
+                            metadata: metadata.clone(),
+                            reverses: Some(data.transaction_id.clone()),
+                            ..new_record(
+                                now,
+                                -change,
+                                EcosystemCreditChangeKind::Reversal,
+                                &transaction_id,
+                                identity,
+                            )
+                        });
+                    entries.push(EcosystemTransactionEntry {
+                        user_id: entry.user_id.clone(),
+                        credit: -change,
+                        kind: EcosystemCreditChangeKind::Reversal,
+                        counterparty: entry.counterparty.clone(),
+                        previous_credit: None,
+                    });
+                }
+                // 冲正导致扣款的账户同样不能透支, 除非显式要求
+                if !data.force && !rule.allow_negative {
+                    for (update, user_id) in accounts.iter().zip(user_ids.iter()) {
+                        let debited = deltas.get(user_id).is_some_and(|delta| *delta < 0);
+                        let overdrawn = update
+                            .account
+                            .as_ref()
+                            .is_some_and(|account| account.available_credit(now) < 0);
+                        if debited && overdrawn {
+                            return Err(FineError::InsufficientCredit);
+                        }
+                    }
+                }
+                linked.reversed_by = Some(transaction_id.clone());
+                Ok((transaction_id.clone(), entries))
+            },
+        )
+        .await?;
+    info!(
+        "Transaction {} reversed by {}: {}",
+        data.transaction_id, transaction_id, data.reason
+    );
+    Ok(Message {
+        message_type: MessageType::EcosytemReverseTransactionResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::ReverseTransactionResponseData {
+            currency: original.currency,
+            transaction_id,
+            reverses: data.transaction_id,
+            entries,
+        })
+        .unwrap(),
+    })
+}
+
+// 查询余额排行榜
+pub async fn get_leaderboard(
+    raw_data: serde_json::Value,
+    redis_conn: &mut MultiplexedConnection,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    let data: ecosystem::GetLeaderboardRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    currency_rule(config, &data.currency)?;
+    let top = storage::ecosystem::get_leaderboard_top(
+        redis_conn,
+        &data.currency,
+        data.top.min(MAX_LEADERBOARD_TOP),
+    )
+    .await?;
+    let entries = top
+        .into_iter()
+        .enumerate()
+        .map(|(i, (user_id, credit))| ecosystem::LeaderboardEntry {
+            rank: i + 1,
+            user_id,
+            credit,
+        })
+        .collect();
+    let mut user_entry = None;
+    if let Some(user_id) = data.user_id {
+        user_entry = storage::ecosystem::get_leaderboard_rank(redis_conn, &data.currency, &user_id)
+            .await?
+            .map(|(rank, credit)| ecosystem::LeaderboardEntry {
+                rank: rank + 1,
+                user_id,
+                credit,
+            });
+    }
+    Ok(Message {
+        message_type: MessageType::EcosytemGetLeaderboardResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::GetLeaderboardResponseData {
+            currency: data.currency,
+            entries,
+            user_entry,
+        })
+        .unwrap(),
+    })
+}
+
+// 按现有账户重建所有货币的余额排行榜
+pub async fn rebuild_leaderboard(
+    redis_conn: &mut MultiplexedConnection,
+    config: &EconomyConfig,
+) -> Result<Message, FineError> {
+    let mut user_counts = HashMap::new();
+    for currency in config.currencies.keys() {
+        let user_count = storage::ecosystem::rebuild_leaderboard(redis_conn, currency).await?;
+        user_counts.insert(currency.clone(), user_count);
+    }
+    Ok(Message {
+        message_type: MessageType::EcosytemRebuildLeaderboardResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::RebuildLeaderboardResponseData { user_counts })
+            .unwrap(),
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,1234 @@
+use std::collections::HashSet;
+
+use serde_json::{json, Value};
+
+use super::*;
+use crate::{config::DEFAULT_CURRENCY, storage::memory::MemoryAccountStore};
+
+// credit: 默认货币; gem: 允许透支; token: 不可转账;
+// coin: 转账收取1%手续费(至少1), 单笔增减不超过1000, 单笔转账不超过5000
+fn test_config() -> EconomyConfig {
+    serde_json::from_value(json!({
+        "currencies": {
+            "credit": {},
+            "gem": { "allow_negative": true },
+            "token": { "transferable": false },
+            "coin": {
+                "limits": {
+                    "set": { "min": 0, "max": 100000 },
+                    "alter": { "max": 1000 },
+                    "transfer": { "max": 5000 }
+                },
+                "transfer_fee": {
+                    "system_account": "bank",
+                    "brackets": [{ "rate_bps": 100, "min_fee": 1 }]
+                }
+            }
+        }
+    }))
+    .unwrap()
+}
+
+fn test_identity() -> ClientIdentity {
+    ClientIdentity {
+        name: "test-server".to_string(),
+        scopes: HashSet::new(),
+        alter_limit: None,
+    }
+}
+
+fn put_account(store: &MemoryAccountStore, currency: &str, user_id: &str, credit: i64) {
+    store.insert_account(currency, user_id, EcosystemUserAccountRecord::new(credit));
+}
+
+async fn put_status(
+    store: &mut MemoryAccountStore,
+    currency: &str,
+    user_id: &str,
+    status: EcosystemAccountStatus,
+) {
+    let mut account = store.get_account(currency, user_id).await.unwrap().unwrap();
+    account.status = status;
+    store.insert_account(currency, user_id, account);
+}
+
+async fn credit_of(store: &mut MemoryAccountStore, currency: &str, user_id: &str) -> Option<i64> {
+    store
+        .get_account(currency, user_id)
+        .await
+        .unwrap()
+        .map(|account| account.credit)
+}
+
+async fn set(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    set_user_credit(data, store, &test_config(), &test_identity()).await
+}
+
+async fn get(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    get_user_credit(data, store, &test_config()).await
+}
+
+async fn alter(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    alter_user_credit(data, store, &test_config(), &test_identity()).await
+}
+
+async fn transfer(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    transfer_user_credit(data, store, &test_config(), &test_identity()).await
+}
+
+async fn hold(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    hold_credit(data, store, &test_config(), &test_identity()).await
+}
+
+async fn capture(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    capture_hold(data, store, &test_config(), &test_identity()).await
+}
+
+async fn reverse(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    reverse_transaction(data, store, &test_config(), &test_identity()).await
+}
+
+async fn batch(store: &mut MemoryAccountStore, data: Value) -> Result<Message, FineError> {
+    batch_alter_user_credit(data, store, &test_config(), &test_identity()).await
+}
+
+#[tokio::test]
+async fn set_creates_missing_account() {
+    let mut store = MemoryAccountStore::default();
+    let resp = set(&mut store, json!({ "user_id": "alice", "credit": 100 }))
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 100);
+    assert_eq!(resp.data["currency"], DEFAULT_CURRENCY);
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(100));
+
+    let history = store.history("credit", "alice");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].kind, EcosystemCreditChangeKind::AdminSet);
+    assert_eq!(history[0].credit, 100);
+    assert_eq!(history[0].source_client.as_deref(), Some("test-server"));
+    assert_eq!(history[0].transaction_id, resp.data["transaction_id"]);
+}
+
+#[tokio::test]
+async fn set_overwrites_existing_credit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 500);
+    let resp = set(
+        &mut store,
+        json!({ "user_id": "alice", "credit": 20, "reason": "reset" }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["credit"], 20);
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(20));
+    let history = store.history("credit", "alice");
+    assert_eq!(
+        history[0].metadata.get("reason").map(String::as_str),
+        Some("reset")
+    );
+}
+
+#[tokio::test]
+async fn set_rejects_negative_credit_unless_allowed() {
+    let mut store = MemoryAccountStore::default();
+    let err = set(&mut store, json!({ "user_id": "alice", "credit": -1 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::InvalidAmount(_)));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, None);
+
+    let resp = set(
+        &mut store,
+        json!({ "currency": "gem", "user_id": "alice", "credit": -1 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["credit"], -1);
+}
+
+#[tokio::test]
+async fn set_respects_currency_limits() {
+    let mut store = MemoryAccountStore::default();
+    let err = set(
+        &mut store,
+        json!({ "currency": "coin", "user_id": "alice", "credit": 100001 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InvalidAmount(_)));
+    assert_eq!(credit_of(&mut store, "coin", "alice").await, None);
+}
+
+#[tokio::test]
+async fn set_rejects_unknown_currency() {
+    let mut store = MemoryAccountStore::default();
+    let err = set(
+        &mut store,
+        json!({ "currency": "dogecoin", "user_id": "alice", "credit": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::UnknownCurrency(currency) if currency == "dogecoin"));
+}
+
+#[tokio::test]
+async fn set_rejects_closed_account() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 0);
+    put_status(
+        &mut store,
+        "credit",
+        "alice",
+        EcosystemAccountStatus::Closed,
+    )
+    .await;
+    let err = set(&mut store, json!({ "user_id": "alice", "credit": 10 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::AccountClosed(_)));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(0));
+    assert!(store.history("credit", "alice").is_empty());
+}
+
+#[tokio::test]
+async fn set_keeps_currencies_separate() {
+    let mut store = MemoryAccountStore::default();
+    set(&mut store, json!({ "user_id": "alice", "credit": 10 }))
+        .await
+        .unwrap();
+    set(
+        &mut store,
+        json!({ "currency": "gem", "user_id": "alice", "credit": 3 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(10));
+    assert_eq!(credit_of(&mut store, "gem", "alice").await, Some(3));
+}
+
+#[tokio::test]
+async fn set_replays_idempotent_request() {
+    let mut store = MemoryAccountStore::default();
+    let request = json!({ "user_id": "alice", "credit": 100, "idempotency_key": "k1" });
+    let first = set(&mut store, request.clone()).await.unwrap();
+    set(&mut store, json!({ "user_id": "alice", "credit": 7 }))
+        .await
+        .unwrap();
+    let replay = set(&mut store, request).await.unwrap();
+    assert_eq!(replay.data, first.data);
+    // 重放不会再次写入
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(7));
+    assert_eq!(store.history("credit", "alice").len(), 2);
+}
+
+#[tokio::test]
+async fn get_returns_credit_and_status() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 42);
+    put_status(
+        &mut store,
+        "credit",
+        "alice",
+        EcosystemAccountStatus::Frozen,
+    )
+    .await;
+    let resp = get(&mut store, json!({ "user_id": "alice" }))
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 42);
+    assert_eq!(resp.data["held"], 0);
+    assert_eq!(resp.data["available"], 42);
+    assert_eq!(resp.data["status"], "frozen");
+}
+
+#[tokio::test]
+async fn get_excludes_held_credit_from_available() {
+    let mut store = MemoryAccountStore::default();
+    let now = chrono::Utc::now().timestamp();
+    let mut account = EcosystemUserAccountRecord::new(100);
+    account.holds = vec![
+        EcosystemCreditHold {
+            hold_id: "active".to_string(),
+            credit: 30,
+            created_at: now,
+            expires_at: now + 3600,
+            reason: String::new(),
+        },
+        EcosystemCreditHold {
+            hold_id: "expired".to_string(),
+            credit: 50,
+            created_at: now - 7200,
+            expires_at: now - 3600,
+            reason: String::new(),
+        },
+    ];
+    store.insert_account("credit", "alice", account);
+    let resp = get(&mut store, json!({ "user_id": "alice" }))
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 100);
+    assert_eq!(resp.data["held"], 30);
+    assert_eq!(resp.data["available"], 70);
+}
+
+#[tokio::test]
+async fn get_reports_missing_user() {
+    let mut store = MemoryAccountStore::default();
+    let err = get(&mut store, json!({ "user_id": "nobody" }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::UserNotFound(user_id) if user_id == "nobody"));
+}
+
+#[tokio::test]
+async fn get_rejects_invalid_payload() {
+    let mut store = MemoryAccountStore::default();
+    let err = get(&mut store, json!({ "currency": "credit" }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::InvalidPayload(_)));
+}
+
+#[tokio::test]
+async fn alter_adds_and_subtracts_credit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    let resp = alter(&mut store, json!({ "user_id": "alice", "credit": 25 }))
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 125);
+    let resp = alter(
+        &mut store,
+        json!({ "user_id": "alice", "credit": -125, "kind": "reward", "reason": "refund" }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["credit"], 0);
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(0));
+
+    let history = store.history("credit", "alice");
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].credit, 25);
+    assert_eq!(history[0].kind, EcosystemCreditChangeKind::Alter);
+    assert_eq!(history[1].credit, -125);
+    assert_eq!(history[1].kind, EcosystemCreditChangeKind::Reward);
+    assert_eq!(
+        history[1].metadata.get("reason").map(String::as_str),
+        Some("refund")
+    );
+}
+
+#[tokio::test]
+async fn alter_reports_missing_user() {
+    let mut store = MemoryAccountStore::default();
+    let err = alter(&mut store, json!({ "user_id": "nobody", "credit": 1 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::UserNotFound(_)));
+    // 增减不会自动开户
+    assert_eq!(credit_of(&mut store, "credit", "nobody").await, None);
+}
+
+#[tokio::test]
+async fn alter_rejects_overdraft_and_leaves_account_unchanged() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 10);
+    let err = alter(&mut store, json!({ "user_id": "alice", "credit": -11 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(10));
+    assert!(store.history("credit", "alice").is_empty());
+}
+
+#[tokio::test]
+async fn alter_allows_overdraft_when_configured() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "gem", "alice", 10);
+    let resp = alter(
+        &mut store,
+        json!({ "currency": "gem", "user_id": "alice", "credit": -30 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["credit"], -20);
+}
+
+#[tokio::test]
+async fn alter_cannot_spend_held_credit() {
+    let mut store = MemoryAccountStore::default();
+    let now = chrono::Utc::now().timestamp();
+    let mut account = EcosystemUserAccountRecord::new(100);
+    account.holds.push(EcosystemCreditHold {
+        hold_id: "bid".to_string(),
+        credit: 80,
+        created_at: now,
+        expires_at: now + 3600,
+        reason: String::new(),
+    });
+    store.insert_account("credit", "alice", account);
+    let err = alter(&mut store, json!({ "user_id": "alice", "credit": -21 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+    let resp = alter(&mut store, json!({ "user_id": "alice", "credit": -20 }))
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 80);
+}
+
+#[tokio::test]
+async fn alter_frozen_account_can_only_receive() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 10);
+    put_status(
+        &mut store,
+        "credit",
+        "alice",
+        EcosystemAccountStatus::Frozen,
+    )
+    .await;
+    let err = alter(&mut store, json!({ "user_id": "alice", "credit": -1 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::AccountFrozen(_)));
+    let resp = alter(&mut store, json!({ "user_id": "alice", "credit": 5 }))
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 15);
+}
+
+#[tokio::test]
+async fn alter_rejects_closed_account() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 0);
+    put_status(
+        &mut store,
+        "credit",
+        "alice",
+        EcosystemAccountStatus::Closed,
+    )
+    .await;
+    let err = alter(&mut store, json!({ "user_id": "alice", "credit": 5 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::AccountClosed(_)));
+}
+
+#[tokio::test]
+async fn alter_respects_currency_and_client_limits() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 5000);
+    let err = alter(
+        &mut store,
+        json!({ "currency": "coin", "user_id": "alice", "credit": -1001 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InvalidAmount(_)));
+
+    let identity = ClientIdentity {
+        alter_limit: Some(50),
+        ..test_identity()
+    };
+    let err = alter_user_credit(
+        json!({ "currency": "coin", "user_id": "alice", "credit": 51 }),
+        &mut store,
+        &test_config(),
+        &identity,
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::Forbidden(_)));
+    assert_eq!(credit_of(&mut store, "coin", "alice").await, Some(5000));
+}
+
+#[tokio::test]
+async fn alter_reports_overflow() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", i64::MAX);
+    let err = alter(&mut store, json!({ "user_id": "alice", "credit": 1 }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::AmountOverflow));
+    assert_eq!(
+        credit_of(&mut store, "credit", "alice").await,
+        Some(i64::MAX)
+    );
+}
+
+#[tokio::test]
+async fn alter_replays_idempotent_request() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    let request = json!({ "user_id": "alice", "credit": -30, "idempotency_key": "order-1" });
+    let first = alter(&mut store, request.clone()).await.unwrap();
+    let replay = alter(&mut store, request).await.unwrap();
+    assert_eq!(replay.data, first.data);
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(70));
+    assert_eq!(store.history("credit", "alice").len(), 1);
+
+    // 不同操作的幂等key互不影响
+    let resp = set(
+        &mut store,
+        json!({ "user_id": "alice", "credit": 1, "idempotency_key": "order-1" }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["credit"], 1);
+}
+
+#[tokio::test]
+async fn alter_failure_is_not_cached_by_idempotency_key() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 10);
+    let request = json!({ "user_id": "alice", "credit": -20, "idempotency_key": "k" });
+    let err = alter(&mut store, request.clone()).await.unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+    alter(&mut store, json!({ "user_id": "alice", "credit": 10 }))
+        .await
+        .unwrap();
+    let resp = alter(&mut store, request).await.unwrap();
+    assert_eq!(resp.data["credit"], 0);
+}
+
+#[tokio::test]
+async fn alter_rejects_idempotency_key_reused_for_different_request() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 100);
+    alter(
+        &mut store,
+        json!({ "user_id": "alice", "credit": -10, "idempotency_key": "order-1" }),
+    )
+    .await
+    .unwrap();
+    let err = alter(
+        &mut store,
+        json!({ "user_id": "bob", "credit": -10, "idempotency_key": "order-1" }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::IdempotencyKeyReused(key) if key == "order-1"));
+    assert_eq!(credit_of(&mut store, "credit", "bob").await, Some(100));
+}
+
+#[tokio::test]
+async fn idempotency_keys_are_scoped_to_client() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    let request = json!({ "user_id": "alice", "credit": -10, "idempotency_key": "order-1" });
+    alter(&mut store, request.clone()).await.unwrap();
+    let other = ClientIdentity {
+        name: "other-server".to_string(),
+        ..test_identity()
+    };
+    let resp = alter_user_credit(request, &mut store, &test_config(), &other)
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 80);
+    assert_eq!(store.history("credit", "alice").len(), 2);
+}
+
+#[tokio::test]
+async fn transfer_moves_credit_and_records_both_sides() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 5);
+    let resp = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 40 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["from_user_credit"], 60);
+    assert_eq!(resp.data["to_user_credit"], 45);
+    assert_eq!(resp.data["fee"], 0);
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(60));
+    assert_eq!(credit_of(&mut store, "credit", "bob").await, Some(45));
+
+    let transaction_id = resp.data["transaction_id"].as_str().unwrap();
+    let out = &store.history("credit", "alice")[0];
+    assert_eq!(out.credit, -40);
+    assert_eq!(out.kind, EcosystemCreditChangeKind::TransferOut);
+    assert_eq!(out.counterparty.as_deref(), Some("bob"));
+    assert_eq!(out.transaction_id, transaction_id);
+    let into = &store.history("credit", "bob")[0];
+    assert_eq!(into.credit, 40);
+    assert_eq!(into.kind, EcosystemCreditChangeKind::TransferIn);
+    assert_eq!(into.counterparty.as_deref(), Some("alice"));
+
+    let transaction = store.transaction(transaction_id).unwrap();
+    assert_eq!(transaction.entries.len(), 2);
+    assert_eq!(
+        transaction
+            .entries
+            .iter()
+            .map(|entry| entry.credit)
+            .sum::<i64>(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn transfer_rejects_insufficient_credit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 10);
+    put_account(&store, "credit", "bob", 0);
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 11 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(10));
+    assert_eq!(credit_of(&mut store, "credit", "bob").await, Some(0));
+    assert!(store.history("credit", "alice").is_empty());
+    assert!(store.history("credit", "bob").is_empty());
+}
+
+#[tokio::test]
+async fn transfer_rejects_invalid_requests() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    put_account(&store, "token", "alice", 100);
+    put_account(&store, "token", "bob", 0);
+
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "alice", "credit": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::SelfTransfer));
+    // 负数金额不能让转账反向进行, 从收款方扣款
+    for credit in [0, -5, i64::MIN] {
+        let err = transfer(
+            &mut store,
+            json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": credit }),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, FineError::InvalidAmount(_)));
+    }
+    let err = transfer(
+        &mut store,
+        json!({ "currency": "token", "from_user_id": "alice", "to_user_id": "bob", "credit": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::NotTransferable(_)));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(100));
+    assert_eq!(credit_of(&mut store, "token", "alice").await, Some(100));
+}
+
+#[tokio::test]
+async fn transfer_rejects_oversized_amounts() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": i64::MAX }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+
+    // 允许透支时, 超大金额在收款方溢出
+    put_account(&store, "gem", "alice", 0);
+    put_account(&store, "gem", "bob", 1);
+    let err = transfer(
+        &mut store,
+        json!({ "currency": "gem", "from_user_id": "alice", "to_user_id": "bob", "credit": i64::MAX }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::AmountOverflow));
+    assert_eq!(credit_of(&mut store, "gem", "alice").await, Some(0));
+    assert_eq!(credit_of(&mut store, "gem", "bob").await, Some(1));
+}
+
+#[tokio::test]
+async fn transfer_respects_currency_transfer_limit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 100000);
+    put_account(&store, "coin", "bob", 0);
+    let err = transfer(
+        &mut store,
+        json!({ "currency": "coin", "from_user_id": "alice", "to_user_id": "bob", "credit": 5001 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InvalidAmount(_)));
+    assert_eq!(credit_of(&mut store, "coin", "alice").await, Some(100000));
+    assert_eq!(credit_of(&mut store, "coin", "bob").await, Some(0));
+
+    // 上限本身是允许的
+    let resp = transfer(
+        &mut store,
+        json!({ "currency": "coin", "from_user_id": "alice", "to_user_id": "bob", "credit": 5000 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["to_user_credit"], 5000);
+}
+
+#[tokio::test]
+async fn transfer_requires_both_accounts() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "nobody", "credit": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::UserNotFound(user_id) if user_id == "nobody"));
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "nobody", "to_user_id": "alice", "credit": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::UserNotFound(user_id) if user_id == "nobody"));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(100));
+    assert_eq!(credit_of(&mut store, "credit", "nobody").await, None);
+}
+
+#[tokio::test]
+async fn transfer_frozen_account_can_receive_but_not_send() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 100);
+    put_status(&mut store, "credit", "bob", EcosystemAccountStatus::Frozen).await;
+
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "bob", "to_user_id": "alice", "credit": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::AccountFrozen(_)));
+    let resp = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 1 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["to_user_credit"], 101);
+}
+
+#[tokio::test]
+async fn transfer_rejects_closed_recipient() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    put_status(&mut store, "credit", "bob", EcosystemAccountStatus::Closed).await;
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 1 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::AccountClosed(user_id) if user_id == "bob"));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(100));
+}
+
+#[tokio::test]
+async fn transfer_charges_fee_to_system_account() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 1000);
+    put_account(&store, "coin", "bob", 0);
+    let resp = transfer(
+        &mut store,
+        json!({ "currency": "coin", "from_user_id": "alice", "to_user_id": "bob", "credit": 500 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["fee"], 5);
+    assert_eq!(resp.data["from_user_credit"], 495);
+    assert_eq!(resp.data["to_user_credit"], 500);
+    // 系统账户不存在时自动开户
+    assert_eq!(credit_of(&mut store, "coin", "bank").await, Some(5));
+
+    let fees: Vec<i64> = store
+        .history("coin", "alice")
+        .iter()
+        .filter(|record| record.kind == EcosystemCreditChangeKind::Fee)
+        .map(|record| record.credit)
+        .collect();
+    assert_eq!(fees, vec![-5]);
+    let transaction = store
+        .transaction(resp.data["transaction_id"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(transaction.entries.len(), 4);
+    assert_eq!(
+        transaction
+            .entries
+            .iter()
+            .map(|entry| entry.credit)
+            .sum::<i64>(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn transfer_fee_counts_towards_available_credit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 100);
+    put_account(&store, "coin", "bob", 0);
+    // 100 + 最低手续费1 超过余额
+    let err = transfer(
+        &mut store,
+        json!({ "currency": "coin", "from_user_id": "alice", "to_user_id": "bob", "credit": 100 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+    assert_eq!(credit_of(&mut store, "coin", "bank").await, None);
+}
+
+#[tokio::test]
+async fn transfer_involving_system_account_is_free() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "bank", 1000);
+    put_account(&store, "coin", "bob", 0);
+    let resp = transfer(
+        &mut store,
+        json!({ "currency": "coin", "from_user_id": "bank", "to_user_id": "bob", "credit": 500 }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["fee"], 0);
+    assert_eq!(resp.data["from_user_credit"], 500);
+}
+
+#[tokio::test]
+async fn transfer_replays_idempotent_request() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    let request = json!({
+        "from_user_id": "alice",
+        "to_user_id": "bob",
+        "credit": 30,
+        "idempotency_key": "trade-1"
+    });
+    let first = transfer(&mut store, request.clone()).await.unwrap();
+    let replay = transfer(&mut store, request).await.unwrap();
+    assert_eq!(replay.data, first.data);
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(70));
+    assert_eq!(credit_of(&mut store, "credit", "bob").await, Some(30));
+}
+
+#[tokio::test]
+async fn reverse_transfer_returns_credit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    let resp = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 30 }),
+    )
+    .await
+    .unwrap();
+    let transaction_id = resp.data["transaction_id"].as_str().unwrap().to_string();
+
+    let reversal = reverse(&mut store, json!({ "transaction_id": transaction_id }))
+        .await
+        .unwrap();
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(100));
+    assert_eq!(credit_of(&mut store, "credit", "bob").await, Some(0));
+    assert_eq!(
+        store
+            .transaction(&transaction_id)
+            .unwrap()
+            .reversed_by
+            .as_ref(),
+        reversal.data["transaction_id"]
+            .as_str()
+            .map(str::to_string)
+            .as_ref()
+    );
+
+    let err = reverse(&mut store, json!({ "transaction_id": transaction_id }))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), "transaction_already_reversed");
+}
+
+#[tokio::test]
+async fn reverse_set_restores_previous_credit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 1000);
+    let resp = set(&mut store, json!({ "user_id": "alice", "credit": 10 }))
+        .await
+        .unwrap();
+    let transaction_id = resp.data["transaction_id"].as_str().unwrap().to_string();
+
+    let reversal = reverse(&mut store, json!({ "transaction_id": transaction_id }))
+        .await
+        .unwrap();
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(1000));
+    assert_eq!(reversal.data["entries"][0]["credit"], 990);
+
+    let history = store.history("credit", "alice");
+    assert_eq!(history[1].kind, EcosystemCreditChangeKind::Reversal);
+    assert_eq!(history[1].credit, 990);
+    assert_eq!(
+        history[1].reverses.as_deref(),
+        Some(transaction_id.as_str())
+    );
+}
+
+#[tokio::test]
+async fn reverse_set_applies_only_the_change_made_by_the_set() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    let resp = set(&mut store, json!({ "user_id": "alice", "credit": 500 }))
+        .await
+        .unwrap();
+    let transaction_id = resp.data["transaction_id"].as_str().unwrap().to_string();
+    alter(&mut store, json!({ "user_id": "alice", "credit": -50 }))
+        .await
+        .unwrap();
+
+    reverse(&mut store, json!({ "transaction_id": transaction_id }))
+        .await
+        .unwrap();
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(50));
+}
+
+#[tokio::test]
+async fn capture_charges_transfer_fee() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 1000);
+    put_account(&store, "coin", "bob", 0);
+    let held = hold(
+        &mut store,
+        json!({ "currency": "coin", "user_id": "alice", "credit": 500 }),
+    )
+    .await
+    .unwrap();
+    let resp = capture(
+        &mut store,
+        json!({
+            "currency": "coin",
+            "user_id": "alice",
+            "hold_id": held.data["hold_id"],
+            "to_user_id": "bob"
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["captured"], 500);
+    assert_eq!(resp.data["fee"], 5);
+    assert_eq!(resp.data["user_credit"], 495);
+    assert_eq!(credit_of(&mut store, "coin", "bob").await, Some(500));
+    assert_eq!(credit_of(&mut store, "coin", "bank").await, Some(5));
+    let transaction = store
+        .transaction(resp.data["transaction_id"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(
+        transaction
+            .entries
+            .iter()
+            .map(|entry| entry.credit)
+            .sum::<i64>(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn capture_fee_cannot_exceed_available_credit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 500);
+    put_account(&store, "coin", "bob", 0);
+    let held = hold(
+        &mut store,
+        json!({ "currency": "coin", "user_id": "alice", "credit": 500 }),
+    )
+    .await
+    .unwrap();
+    let err = capture(
+        &mut store,
+        json!({
+            "currency": "coin",
+            "user_id": "alice",
+            "hold_id": held.data["hold_id"],
+            "to_user_id": "bob"
+        }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+    // 结算失败时冻结款保留
+    let account = store.get_account("coin", "alice").await.unwrap().unwrap();
+    assert_eq!(account.holds.len(), 1);
+    assert_eq!(credit_of(&mut store, "coin", "bob").await, Some(0));
+}
+
+#[tokio::test]
+async fn capture_cannot_overdraw_after_balance_was_set_lower() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    let held = hold(&mut store, json!({ "user_id": "alice", "credit": 50 }))
+        .await
+        .unwrap();
+    set(&mut store, json!({ "user_id": "alice", "credit": 0 }))
+        .await
+        .unwrap();
+    let err = capture(
+        &mut store,
+        json!({ "user_id": "alice", "hold_id": held.data["hold_id"], "to_user_id": "bob" }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InsufficientCredit));
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(0));
+    assert_eq!(credit_of(&mut store, "credit", "bob").await, Some(0));
+}
+
+#[tokio::test]
+async fn hold_rejects_amount_over_transfer_limit() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 10000);
+    let err = hold(
+        &mut store,
+        json!({ "currency": "coin", "user_id": "alice", "credit": 5001 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InvalidAmount(_)));
+    let account = store.get_account("coin", "alice").await.unwrap().unwrap();
+    assert!(account.holds.is_empty());
+}
+
+#[tokio::test]
+async fn capture_checks_transfer_limit_in_effect_at_capture() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "coin", "alice", 10000);
+    put_account(&store, "coin", "bob", 0);
+    let held = hold(
+        &mut store,
+        json!({ "currency": "coin", "user_id": "alice", "credit": 3000 }),
+    )
+    .await
+    .unwrap();
+
+    let mut config = test_config();
+    config
+        .currencies
+        .get_mut("coin")
+        .unwrap()
+        .limits
+        .transfer
+        .max = Some(2000);
+    let err = capture_hold(
+        json!({
+            "currency": "coin",
+            "user_id": "alice",
+            "hold_id": held.data["hold_id"],
+            "to_user_id": "bob"
+        }),
+        &mut store,
+        &config,
+        &test_identity(),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::InvalidAmount(_)));
+    assert_eq!(credit_of(&mut store, "coin", "alice").await, Some(10000));
+    assert_eq!(credit_of(&mut store, "coin", "bob").await, Some(0));
+}
+
+#[tokio::test]
+async fn release_hold_returns_credit_to_available() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    let held = hold(&mut store, json!({ "user_id": "alice", "credit": 30 }))
+        .await
+        .unwrap();
+    let data = json!({ "user_id": "alice", "hold_id": held.data["hold_id"] });
+    let resp = release_hold(data.clone(), &mut store, &test_config())
+        .await
+        .unwrap();
+    assert_eq!(resp.data["released"], 30);
+    assert_eq!(resp.data["available"], 100);
+    let err = release_hold(data, &mut store, &test_config())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::HoldNotFound(_)));
+}
+
+#[tokio::test]
+async fn create_account_rejects_existing_account() {
+    let mut store = MemoryAccountStore::default();
+    let data = json!({ "user_id": "alice" });
+    let resp = create_account(data.clone(), &mut store, &test_config(), &test_identity())
+        .await
+        .unwrap();
+    assert_eq!(resp.data["credit"], 0);
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(0));
+    assert!(store.history("credit", "alice").is_empty());
+    let err = create_account(data, &mut store, &test_config(), &test_identity())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::AccountExists(_)));
+}
+
+#[tokio::test]
+async fn account_lifecycle_freeze_unfreeze_close() {
+    let mut store = MemoryAccountStore::default();
+    let config = test_config();
+    put_account(&store, "credit", "alice", 10);
+    put_account(&store, "credit", "bob", 0);
+    let data = json!({ "user_id": "alice" });
+    let resp = freeze_account(data.clone(), &mut store, &config)
+        .await
+        .unwrap();
+    assert_eq!(resp.data["status"], "frozen");
+    let err = transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 10 }),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, FineError::AccountFrozen(_)));
+
+    unfreeze_account(data.clone(), &mut store, &config)
+        .await
+        .unwrap();
+    // 余额不为0时不能注销
+    let err = close_account(data.clone(), &mut store, &config)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::AccountNotEmpty(_)));
+    transfer(
+        &mut store,
+        json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 10 }),
+    )
+    .await
+    .unwrap();
+    let resp = close_account(data.clone(), &mut store, &config)
+        .await
+        .unwrap();
+    assert_eq!(resp.data["status"], "closed");
+    let err = unfreeze_account(data, &mut store, &config)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FineError::AccountClosed(_)));
+}
+
+#[tokio::test]
+async fn batch_all_or_nothing_writes_nothing_when_an_entry_fails() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    let data = json!({
+        "entries": [
+            { "user_id": "alice", "credit": -50 },
+            { "user_id": "bob", "credit": -10 }
+        ],
+        "idempotency_key": "batch-1"
+    });
+    let resp = batch(&mut store, data.clone()).await.unwrap();
+    assert_eq!(resp.data["transaction_id"], Value::Null);
+    assert_eq!(resp.data["results"][0]["status"], "skipped");
+    assert_eq!(resp.data["results"][1]["status"], "failed");
+    assert_eq!(
+        resp.data["results"][1]["error"]["code"],
+        "insufficient_credit"
+    );
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(100));
+    assert!(store.history("credit", "alice").is_empty());
+
+    // 未生效的批量请求不保存幂等结果, 条件满足后可用同一个key重试
+    put_account(&store, "credit", "bob", 10);
+    let resp = batch(&mut store, data).await.unwrap();
+    assert!(resp.data["transaction_id"].is_string());
+    assert_eq!(resp.data["results"][0]["status"], "applied");
+    assert_eq!(resp.data["results"][1]["status"], "applied");
+    assert_eq!(credit_of(&mut store, "credit", "alice").await, Some(50));
+    assert_eq!(credit_of(&mut store, "credit", "bob").await, Some(0));
+}
+
+#[tokio::test]
+async fn batch_best_effort_skips_failed_entries() {
+    let mut store = MemoryAccountStore::default();
+    put_account(&store, "credit", "alice", 100);
+    put_account(&store, "credit", "bob", 0);
+    let resp = batch(
+        &mut store,
+        json!({
+            "entries": [
+                { "user_id": "alice", "credit": -50 },
+                { "user_id": "bob", "credit": -10 }
+            ],
+            "mode": "best_effort"
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.data["results"][0]["status"], "applied");
+    assert_eq!(resp.data["results"][0]["credit"], 50);
+    assert_eq!(resp.data["results"][1]["status"], "failed");
+    let history = store.history("credit", "alice");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].transaction_id, resp.data["transaction_id"]);
+    assert!(store.history("credit", "bob").is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_transfers_keep_total_credit() {
+    const ACCOUNTS: usize = 8;
+    const TRANSFERS: usize = 400;
+    let store = MemoryAccountStore::default();
+    let user_ids: Vec<String> = (0..ACCOUNTS).map(|i| format!("user{}", i)).collect();
+    for user_id in user_ids.iter() {
+        put_account(&store, "credit", user_id, 1000);
+    }
+
+    let mut tasks = vec![];
+    for i in 0..TRANSFERS {
+        let mut store = store.clone();
+        let request = json!({
+            "from_user_id": user_ids[i % ACCOUNTS],
+            "to_user_id": user_ids[(i * 3 + 1) % ACCOUNTS],
+            "credit": (i % 50) as i64 + 1,
+        });
+        tasks.push(tokio::spawn(async move {
+            transfer_user_credit(request, &mut store, &test_config(), &test_identity()).await
+        }));
+    }
+    let mut succeeded = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(_) => succeeded += 1,
+            Err(FineError::InsufficientCredit | FineError::SelfTransfer) => {}
+            Err(err) => panic!("transfer failed: {}", err),
+        }
+    }
+    assert!(succeeded >= TRANSFERS / 2, "only {} succeeded", succeeded);
+
+    let mut store = store;
+    let mut total = 0;
+    for user_id in user_ids.iter() {
+        let credit = credit_of(&mut store, "credit", user_id).await.unwrap();
+        // 每个账户的余额与其资产变动记录一致, 重试不会重复写入记录
+        let recorded: i64 = store
+            .history("credit", user_id)
+            .iter()
+            .map(|record| record.credit)
+            .sum();
+        assert_eq!(credit, 1000 + recorded);
+        assert!(credit >= 0);
+        total += credit;
+    }
+    assert_eq!(total, 1000 * ACCOUNTS as i64);
+}
@@ -1 +1,4 @@
+pub mod admin;
 pub mod ecosystem;
+pub mod schedule;
+pub mod subscription;
@@ -0,0 +1,155 @@
+use crate::{
+    auth::{ClientIdentity, Scope},
+    config::EconomyConfig,
+    error::FineError,
+    handler::ecosystem::{check_alter_amount, check_transfer, currency_rule, random_id},
+    message::{ecosystem, Message, MessageType},
+    model::ecosystem::{
+        EcosystemScheduledAction, EcosystemScheduledPayment, EcosystemScheduledPaymentStatus,
+    },
+    storage,
+};
+use redis::aio::MultiplexedConnection;
+use tracing::info;
+
+// 周期任务的最短与最长间隔
+const MIN_SCHEDULE_INTERVAL_SECONDS: i64 = 60;
+pub const MAX_SCHEDULE_INTERVAL_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+// 取消任务时的最大重试次数
+const MAX_CANCEL_RETRIES: usize = 8;
+
+// 创建定时支付, 载荷在创建时按创建者的权限与限额校验一次
+pub async fn schedule_payment(
+    raw_data: serde_json::Value,
+    redis_conn: &mut MultiplexedConnection,
+    config: &EconomyConfig,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::SchedulePaymentRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    if !data.payload.is_object() {
+        return Err(FineError::InvalidSchedule(
+            "payload must be an object".to_string(),
+        ));
+    }
+    let scope = match data.action {
+        EcosystemScheduledAction::Alter => {
+            let req: ecosystem::AlterUserCreditRequestData =
+                serde_json::from_value(data.payload.clone()).map_err(FineError::InvalidPayload)?;
+            check_alter_amount(currency_rule(config, &req.currency)?, identity, req.credit)?;
+            Scope::EcoAlter
+        }
+        EcosystemScheduledAction::Transfer => {
+            let req: ecosystem::TransferCreditRequestData =
+                serde_json::from_value(data.payload.clone()).map_err(FineError::InvalidPayload)?;
+            check_transfer(currency_rule(config, &req.currency)?, &req)?;
+            Scope::EcoTransfer
+        }
+    };
+    if !identity.has_scope(scope) {
+        return Err(FineError::Forbidden(format!(
+            "missing scope {}",
+            scope.as_str()
+        )));
+    }
+    if data.interval_seconds.is_some_and(|interval| {
+        !(MIN_SCHEDULE_INTERVAL_SECONDS..=MAX_SCHEDULE_INTERVAL_SECONDS).contains(&interval)
+    }) {
+        return Err(FineError::InvalidSchedule(format!(
+            "interval must be between {} and {} seconds",
+            MIN_SCHEDULE_INTERVAL_SECONDS, MAX_SCHEDULE_INTERVAL_SECONDS
+        )));
+    }
+    if data.max_runs == Some(0) {
+        return Err(FineError::InvalidSchedule(
+            "max runs must be positive".to_string(),
+        ));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let job = EcosystemScheduledPayment {
+        job_id: random_id(),
+        action: data.action,
+        payload: data.payload,
+        next_run_at: data.run_at.unwrap_or(now),
+        interval_seconds: data.interval_seconds,
+        max_runs: data.max_runs,
+        runs: 0,
+        created_by: identity.name.clone(),
+        created_at: now,
+        last_run_at: None,
+        last_error: None,
+        status: EcosystemScheduledPaymentStatus::Active,
+    };
+    // 任务id随机生成, 不会与已有任务冲突
+    storage::schedule::replace_job(redis_conn, &job.job_id, None, Some(&job)).await?;
+    info!(
+        "Scheduled payment {} created by {}",
+        job.job_id, identity.name
+    );
+    Ok(Message {
+        message_type: MessageType::EcosytemSchedulePaymentResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::ScheduledPaymentResponseData { job }).unwrap(),
+    })
+}
+
+// 客户端只能查看和取消自己创建的任务, 管理员不受限制
+fn can_manage(identity: &ClientIdentity, job: &EcosystemScheduledPayment) -> bool {
+    identity.has_scope(Scope::Admin) || job.created_by == identity.name
+}
+
+// 列出定时支付
+pub async fn list_scheduled_payments(
+    raw_data: serde_json::Value,
+    redis_conn: &mut MultiplexedConnection,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::ListScheduledPaymentsRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    let mut jobs = storage::schedule::list_jobs(redis_conn).await?;
+    jobs.retain(|job| {
+        can_manage(identity, job)
+            && data
+                .created_by
+                .as_ref()
+                .is_none_or(|created_by| &job.created_by == created_by)
+    });
+    Ok(Message {
+        message_type: MessageType::EcosytemListScheduledPaymentsResponse,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::ListScheduledPaymentsResponseData { jobs }).unwrap(),
+    })
+}
+
+// 取消定时支付, 已经开始的本次执行不受影响; 取消执行失败的任务即删除其记录
+pub async fn cancel_scheduled_payment(
+    raw_data: serde_json::Value,
+    redis_conn: &mut MultiplexedConnection,
+    identity: &ClientIdentity,
+) -> Result<Message, FineError> {
+    let data: ecosystem::CancelScheduledPaymentRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    for _ in 0..MAX_CANCEL_RETRIES {
+        // 他人的任务与不存在的任务返回相同的错误, 不暴露任务是否存在
+        let (raw, job) = storage::schedule::get_job(redis_conn, &data.job_id)
+            .await?
+            .filter(|(_, job)| can_manage(identity, job))
+            .ok_or_else(|| FineError::ScheduledPaymentNotFound(data.job_id.clone()))?;
+        // 期间任务被执行器推进时重新读取
+        if storage::schedule::replace_job(redis_conn, &data.job_id, Some(&raw), None).await? {
+            info!("Scheduled payment {} cancelled", data.job_id);
+            return Ok(Message {
+                message_type: MessageType::EcosytemCancelScheduledPaymentResponse,
+                request_id: None,
+                data: serde_json::to_value(ecosystem::ScheduledPaymentResponseData { job })
+                    .unwrap(),
+            });
+        }
+    }
+    Err(FineError::TooManyConflicts)
+}
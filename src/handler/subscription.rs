@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+
+use crate::{
+    error::FineError,
+    events::Subscription,
+    message::{ecosystem, Message, MessageType},
+};
+
+// 单个连接最多订阅的用户数
+const MAX_SUBSCRIBED_USERS: usize = 1000;
+
+// 订阅指定用户或所有用户的余额变动事件
+pub fn subscribe(
+    raw_data: serde_json::Value,
+    subscription: &Mutex<Subscription>,
+) -> Result<Message, FineError> {
+    let data: ecosystem::SubscriptionRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    let mut subscription = subscription.lock().unwrap();
+    let mut user_ids = subscription.user_ids.clone();
+    user_ids.extend(data.user_ids);
+    if user_ids.len() > MAX_SUBSCRIBED_USERS {
+        return Err(FineError::TooManySubscriptions);
+    }
+    subscription.user_ids = user_ids;
+    subscription.all |= data.all;
+    Ok(subscription_response(
+        MessageType::EcosytemSubscribeResponse,
+        &subscription,
+    ))
+}
+
+// 取消订阅
+pub fn unsubscribe(
+    raw_data: serde_json::Value,
+    subscription: &Mutex<Subscription>,
+) -> Result<Message, FineError> {
+    let data: ecosystem::SubscriptionRequestData =
+        serde_json::from_value(raw_data).map_err(FineError::InvalidPayload)?;
+
+    let mut subscription = subscription.lock().unwrap();
+    for user_id in data.user_ids.iter() {
+        subscription.user_ids.remove(user_id);
+    }
+    if data.all {
+        subscription.all = false;
+        subscription.user_ids.clear();
+    }
+    Ok(subscription_response(
+        MessageType::EcosytemUnsubscribeResponse,
+        &subscription,
+    ))
+}
+
+fn subscription_response(message_type: MessageType, subscription: &Subscription) -> Message {
+    let mut user_ids: Vec<String> = subscription.user_ids.iter().cloned().collect();
+    user_ids.sort();
+    Message {
+        message_type,
+        request_id: None,
+        data: serde_json::to_value(ecosystem::SubscriptionResponseData {
+            all: subscription.all,
+            user_ids,
+        })
+        .unwrap(),
+    }
+}
@@ -0,0 +1,40 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{
+    auth::{self, Scope},
+    error::FineError,
+    message::{admin::ListConnectionsResponseData, common::CommonErrorResponseData},
+    FineState,
+};
+
+// GET /connections: 列出本实例上已连接的服务器, 需要拥有 admin 权限的客户端token
+pub async fn list_connections(
+    State(fine_state): State<Arc<FineState>>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let identity = auth::extract_token(&headers, &query)
+        .and_then(|token| fine_state.client_credentials.get(&token));
+    let (status, err) = match identity {
+        Some(identity) if identity.has_scope(Scope::Admin) => {
+            return Json(ListConnectionsResponseData {
+                connections: fine_state.connections.list(),
+                recent_events: fine_state.connections.recent_events(),
+            })
+            .into_response();
+        }
+        Some(_) => (
+            StatusCode::FORBIDDEN,
+            FineError::Forbidden(format!("missing scope {}", Scope::Admin.as_str())),
+        ),
+        None => (StatusCode::UNAUTHORIZED, FineError::Unauthorized),
+    };
+    (status, Json(CommonErrorResponseData::from(err))).into_response()
+}
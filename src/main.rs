@@ -1,19 +1,43 @@
 use axum::{routing::get, Router, Server};
 
-use std::{env, net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, env, net::SocketAddr, sync::Arc, time::Duration};
 
 use dotenvy::dotenv;
+use tokio::sync::broadcast;
+use tracing::info;
 
+mod auth;
+#[cfg(feature = "qq-bot")]
 mod bot;
+mod config;
+mod error;
+mod events;
 mod handler;
+mod http;
 mod message;
 mod model;
+mod registry;
+mod scheduler;
 mod socket;
+mod storage;
 
 pub struct FineState {
-    redis_client: redis::Client,
+    redis_pool: storage::pool::RedisPool,
+    // 游戏服务器的预共享token -> 服务器身份
+    client_credentials: HashMap<String, auth::ClientIdentity>,
+    economy_config: config::EconomyConfig,
+    // 单个websocket连接上同时处理的请求数上限
+    socket_max_in_flight: usize,
+    socket_heartbeat: socket::HeartbeatConfig,
+    // 所有服务实例上的余额变动事件, 由各连接按订阅过滤后推送
+    credit_events: broadcast::Sender<Arc<model::ecosystem::EcosystemCreditChangedEvent>>,
+    // 本实例上已连接的游戏服务器
+    connections: registry::ConnectionRegistry,
 }
 
+// 每个连接最多积压的余额变动事件数, 超出时丢弃最旧的事件
+const CREDIT_EVENT_BUFFER: usize = 1024;
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
@@ -21,33 +45,86 @@ async fn main() {
     tracing_subscriber::fmt().pretty().init();
     let host = env::var("HOST").unwrap_or("127.0.0.1".to_string());
     let port = env::var("PORT").unwrap_or("3000".to_string());
+    let socket_max_in_flight: usize = env::var("SOCKET_MAX_IN_FLIGHT")
+        .unwrap_or("16".to_string())
+        .parse()
+        .expect("illegal socket max in flight");
+    assert!(
+        socket_max_in_flight >= 1,
+        "SOCKET_MAX_IN_FLIGHT must be at least 1"
+    );
+    let socket_ping_interval_seconds: u64 = env::var("SOCKET_PING_INTERVAL_SECONDS")
+        .unwrap_or("30".to_string())
+        .parse()
+        .expect("illegal socket ping interval");
+    assert!(
+        socket_ping_interval_seconds >= 1,
+        "SOCKET_PING_INTERVAL_SECONDS must be at least 1"
+    );
+    let socket_heartbeat = socket::HeartbeatConfig {
+        ping_interval: Duration::from_secs(socket_ping_interval_seconds),
+        max_missed_pongs: env::var("SOCKET_MAX_MISSED_PONGS")
+            .unwrap_or("3".to_string())
+            .parse()
+            .expect("illegal socket max missed pongs"),
+        // 0 表示不因空闲关闭连接
+        idle_timeout: match env::var("SOCKET_IDLE_TIMEOUT_SECONDS")
+            .unwrap_or("0".to_string())
+            .parse()
+            .expect("illegal socket idle timeout")
+        {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        },
+    };
+    let scheduler_poll_seconds: u64 = env::var("SCHEDULER_POLL_SECONDS")
+        .unwrap_or("5".to_string())
+        .parse()
+        .expect("illegal scheduler poll seconds");
+    assert!(
+        scheduler_poll_seconds >= 1,
+        "SCHEDULER_POLL_SECONDS must be at least 1"
+    );
+
+    let redis_pool_size: usize = env::var("REDIS_POOL_SIZE")
+        .unwrap_or("8".to_string())
+        .parse()
+        .expect("illegal redis pool size");
+    let redis_retry = storage::pool::RetryConfig {
+        max_retries: env::var("REDIS_MAX_RETRIES")
+            .unwrap_or("3".to_string())
+            .parse()
+            .expect("illegal redis max retries"),
+        base_delay: Duration::from_millis(
+            env::var("REDIS_RETRY_BASE_MS")
+                .unwrap_or("100".to_string())
+                .parse()
+                .expect("illegal redis retry base delay"),
+        ),
+        max_delay: Duration::from_millis(
+            env::var("REDIS_RETRY_MAX_MS")
+                .unwrap_or("2000".to_string())
+                .parse()
+                .expect("illegal redis retry max delay"),
+        ),
+    };
 
     let redis_host = env::var("REDIS_HOST").unwrap();
     let redis_port = env::var("REDIS_PORT").unwrap();
 
-    // qq client
-    let uin: i64 = env::var("UIN")
-        .expect("failed to read uin")
-        .parse()
-        .expect("illegal uin");
-    let password = env::var("PASSWORD").expect("failed to read password");
-    let super_users = env::var("SUPER_USERS")
-        .expect("failed to read super users")
-        .split(',')
-        .map(|s| s.parse::<u64>().unwrap())
-        .collect::<Vec<_>>();
-    let allowed_groups = env::var("ALLOWED_GROUPS")
-        .expect("failed to read allowed groups")
-        .split(',')
-        .map(|s| s.parse::<u64>().unwrap())
-        .collect::<Vec<_>>();
-
-    tokio::spawn(bot::qq::qq_bot_client(
-        uin,
-        password,
-        super_users,
-        allowed_groups,
-    ));
+    let client_credentials = auth::parse_client_credentials(
+        &env::var("CLIENT_TOKENS").expect("failed to read client tokens"),
+        &env::var("CLIENT_SCOPES").unwrap_or_default(),
+        &env::var("CLIENT_ALTER_LIMITS").unwrap_or_default(),
+    );
+
+    // 留空与不设置相同, 只使用默认货币
+    let economy_config = config::EconomyConfig::load(
+        env::var("ECONOMY_CONFIG")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .as_deref(),
+    );
 
     // redis client
     let redis_password = env::var("REDIS_PASSWORD").unwrap();
@@ -57,10 +134,71 @@ async fn main() {
         redis_password, redis_host, redis_port
     ))
     .unwrap();
-    let service_state = Arc::new(FineState { redis_client });
+
+    // 事件订阅使用独立的 pub/sub 连接, 不占用连接池
+    let (credit_events, _) = broadcast::channel(CREDIT_EVENT_BUFFER);
+    tokio::spawn(events::run_event_listener(
+        redis_client.clone(),
+        credit_events.clone(),
+    ));
+
+    let redis_pool = storage::pool::RedisPool::new(redis_client, redis_pool_size, redis_retry);
+
+    // 启动前迁移旧格式的账户数据
+    let mut redis_conn = redis_pool.get().await.expect("failed to connect to redis");
+    let migrated = storage::ecosystem::migrate_legacy_accounts(redis_conn.conn())
+        .await
+        .expect("failed to migrate legacy accounts");
+    if migrated > 0 {
+        info!("Migrated {} legacy accounts", migrated);
+    }
+
+    let service_state = Arc::new(FineState {
+        redis_pool,
+        client_credentials,
+        economy_config,
+        socket_max_in_flight,
+        socket_heartbeat,
+        credit_events,
+        connections: registry::ConnectionRegistry::default(),
+    });
+
+    // qq client, 可以通过连接登记向游戏服务器发送通知
+    #[cfg(feature = "qq-bot")]
+    {
+        let uin: i64 = env::var("UIN")
+            .expect("failed to read uin")
+            .parse()
+            .expect("illegal uin");
+        let password = env::var("PASSWORD").expect("failed to read password");
+        let super_users = env::var("SUPER_USERS")
+            .expect("failed to read super users")
+            .split(',')
+            .map(|s| s.parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+        let allowed_groups = env::var("ALLOWED_GROUPS")
+            .expect("failed to read allowed groups")
+            .split(',')
+            .map(|s| s.parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+
+        tokio::spawn(bot::qq::qq_bot_client(
+            uin,
+            password,
+            super_users,
+            allowed_groups,
+            service_state.clone(),
+        ));
+    }
+
+    tokio::spawn(scheduler::run_scheduler(
+        service_state.clone(),
+        Duration::from_secs(scheduler_poll_seconds),
+    ));
 
     let app = Router::new()
         .route("/socket", get(socket::socket_upgrader))
+        .route("/connections", get(http::list_connections))
         .with_state(service_state);
     Server::bind(&format!("{}:{}", host, port).parse().unwrap())
         .serve(app.into_make_service_with_connect_info::<SocketAddr>())
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+use crate::registry::{ConnectionEvent, ConnectionInfo};
+
+// 列出已连接服务器的返回报文载荷
+#[derive(Serialize)]
+pub struct ListConnectionsResponseData {
+    pub connections: Vec<ConnectionInfo>,
+    pub recent_events: Vec<ConnectionEvent>, // 最近的连接与断开事件
+}
+
+// 向指定服务器发送通知的报文载荷
+#[derive(Deserialize)]
+pub struct SendToServerRequestData {
+    pub server_name: String,
+    // 原样作为 admin_server_notice 的载荷发送
+    pub data: serde_json::Value,
+}
+
+// 向指定服务器发送通知的返回报文载荷
+#[derive(Serialize)]
+pub struct SendToServerResponseData {
+    pub server_name: String,
+    pub delivered: usize, // 收到通知的连接数, 服务器不在线时为0
+    pub failed: Vec<u64>, // 发送队列已满或正在关闭而未能送达的连接id
+}
@@ -1,18 +1,18 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
-pub struct CommonSuccessResponseData {
-    pub message: String,
-}
+use crate::error::FineError;
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CommonErrorResponseData {
+    pub code: String, // 稳定的错误码, 供客户端判断错误类型
     pub message: String,
 }
 
-// TODO 为各类错误实现 CommonErrorResponseData 的 From trait
-impl From<String> for CommonErrorResponseData {
-    fn from(message: String) -> Self {
-        CommonErrorResponseData { message }
+impl From<FineError> for CommonErrorResponseData {
+    fn from(err: FineError) -> Self {
+        CommonErrorResponseData {
+            code: err.code().to_string(),
+            message: err.to_string(),
+        }
     }
 }
@@ -1,70 +1,468 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
-use crate::model::ecosystem::EcosystemUserCreditAlterRecord;
+use super::common::CommonErrorResponseData;
+use crate::{
+    config::DEFAULT_CURRENCY,
+    model::ecosystem::{
+        EcosystemAccountStatus, EcosystemCreditChangeKind, EcosystemScheduledAction,
+        EcosystemScheduledPayment, EcosystemTransactionEntry, EcosystemUserCreditAlterRecord,
+    },
+};
 
 // 修改用户账户余额的报文载荷
 #[derive(Deserialize)]
 pub struct SetUserCreditRequestData {
+    // 货币名, 不填为默认货币
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub user_id: String,
-    pub credit: i32,
+    pub credit: i64,
+    // 备注, 保存在资产变动记录的 metadata.reason 中
     #[serde(default = "default_resource")]
     pub reason: String,
+    // 附带在资产变动记录中的自定义信息
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // 客户端重试时携带相同的幂等key, 避免重复扣款
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
 }
 
 // 修改用户余额的返回报文载荷
 #[derive(Serialize)]
 pub struct SetUserCreditResponseData {
+    pub currency: String,
     pub user_id: String,
-    pub credit: i32, // 返回修改后的值
+    pub credit: i64, // 返回修改后的值
+    pub transaction_id: String,
 }
 
 fn default_resource() -> String {
     "".to_string()
 }
 
+fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
+}
+
+// 开户的报文载荷
+#[derive(Deserialize)]
+pub struct CreateAccountRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub user_id: String,
+    // 初始余额
+    #[serde(default)]
+    pub credit: i64,
+    #[serde(default = "default_resource")]
+    pub reason: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+// 开户的返回报文载荷
+#[derive(Serialize)]
+pub struct CreateAccountResponseData {
+    pub currency: String,
+    pub user_id: String,
+    pub credit: i64,
+    pub transaction_id: Option<String>, // 没有初始余额时为null
+}
+
+// 冻结, 解冻, 注销账户的报文载荷
+#[derive(Deserialize)]
+pub struct AccountStatusRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub user_id: String,
+    #[serde(default = "default_resource")]
+    pub reason: String,
+}
+
+// 冻结, 解冻, 注销账户的返回报文载荷
+#[derive(Serialize)]
+pub struct AccountStatusResponseData {
+    pub currency: String,
+    pub user_id: String,
+    pub status: EcosystemAccountStatus, // 修改后的状态
+}
+
 // 获取用户余额的报文载荷
 #[derive(Deserialize)]
 pub struct GetUserCreditRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub user_id: String,
 }
 
 // 获取用户余额的返回报文载荷
 #[derive(Serialize)]
 pub struct GetUserCreditResponseData {
+    pub currency: String,
+    pub user_id: String,
+    pub credit: i64,    // 总余额
+    pub held: i64,      // 未过期的冻结款
+    pub available: i64, // 可用余额
+    pub status: EcosystemAccountStatus,
+}
+
+// 分页查询用户资产变动记录的报文载荷
+#[derive(Deserialize)]
+pub struct GetUserCreditHistoryRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub user_id: String,
+    // 上一页返回的 next_cursor, 首页不填
+    pub cursor: Option<usize>,
+    #[serde(default = "default_history_limit")]
+    pub limit: usize,
+    // 时间范围(秒级时间戳, 闭区间)
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    // 只返回该类型的记录
+    pub kind: Option<EcosystemCreditChangeKind>,
+    // 只返回与该用户之间的记录
+    pub counterparty: Option<String>,
+    // 只返回变动原因(metadata.reason)以此开头的记录
+    pub reason_prefix: Option<String>,
+}
+
+fn default_history_limit() -> usize {
+    20
+}
+
+// 分页查询用户资产变动记录的返回报文载荷, 记录从新到旧排列
+#[derive(Serialize)]
+pub struct GetUserCreditHistoryResponseData {
+    pub currency: String,
     pub user_id: String,
-    pub credit: i32,
     pub alter_records: Vec<EcosystemUserCreditAlterRecord>,
+    pub next_cursor: Option<usize>, // 没有更多记录时为null
 }
 
 // 增加或减少用户余额的报文载荷
 #[derive(Deserialize)]
 pub struct AlterUserCreditRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub user_id: String,
-    pub credit: i32,
+    pub credit: i64,
+    #[serde(default)]
+    pub kind: AlterKind,
     #[serde(default = "default_resource")]
     pub reason: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+// 增减余额时可以指定的变动类型
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AlterKind {
+    #[default]
+    Alter,
+    Reward,
+}
+
+impl From<AlterKind> for EcosystemCreditChangeKind {
+    fn from(kind: AlterKind) -> Self {
+        match kind {
+            AlterKind::Alter => EcosystemCreditChangeKind::Alter,
+            AlterKind::Reward => EcosystemCreditChangeKind::Reward,
+        }
+    }
 }
 
 // 增加或减少用户余额的返回报文载荷
 #[derive(Serialize)]
 pub struct AlterUserCreditResponseData {
+    pub currency: String,
+    pub user_id: String,
+    pub credit: i64,
+    pub transaction_id: String,
+}
+
+// 批量增减余额的报文载荷, 例如对局结束后给所有玩家发放奖励
+#[derive(Deserialize)]
+pub struct BatchAlterRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub entries: Vec<BatchAlterEntry>,
+    #[serde(default)]
+    pub mode: BatchMode,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+// 批量增减余额中的一项
+#[derive(Deserialize)]
+pub struct BatchAlterEntry {
+    pub user_id: String,
+    pub credit: i64,
+    #[serde(default)]
+    pub kind: AlterKind,
+    #[serde(default = "default_resource")]
+    pub reason: String,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+// 批量操作的执行方式
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    // 任一项失败时全部不生效
+    #[default]
+    AllOrNothing,
+    // 跳过失败的项, 其余照常生效
+    BestEffort,
+}
+
+// 批量增减余额的返回报文载荷, results 与请求中的 entries 一一对应
+#[derive(Serialize)]
+pub struct BatchAlterResponseData {
+    pub currency: String,
+    pub mode: BatchMode,
+    pub transaction_id: Option<String>, // 没有任何一项生效时为null
+    pub results: Vec<BatchEntryResult>,
+}
+
+// 批量操作中单项的结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchEntryResult {
     pub user_id: String,
-    pub credit: i32,
+    pub status: BatchEntryStatus,
+    pub credit: Option<i64>, // 生效后的余额
+    pub error: Option<CommonErrorResponseData>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchEntryStatus {
+    Applied,
+    Failed,
+    // 本项没有问题, 但因其他项失败而未生效
+    Skipped,
 }
 
 // 用户转账请求报文载荷
 #[derive(Deserialize)]
 pub struct TransferCreditRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub from_user_id: String,
     pub to_user_id: String,
-    pub credit: i32,
+    pub credit: i64,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
 }
 
 // 用户转账返回报文载荷
 #[derive(Serialize)]
 pub struct TransferCreditResponseData {
+    pub currency: String,
     pub from_user_id: String,
-    pub from_user_credit: i32, // 转账后的余额
+    pub from_user_credit: i64, // 转账后的余额
+    pub to_user_id: String,
+    pub to_user_credit: i64,
+    pub fee: i64, // 转出方额外支付的手续费
+    pub transaction_id: String,
+}
+
+// 冻结用户部分余额的报文载荷
+#[derive(Deserialize)]
+pub struct HoldCreditRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub user_id: String,
+    pub credit: i64,
+    // 冻结款的有效期, 过期后自动释放
+    #[serde(default = "default_hold_ttl_seconds")]
+    pub ttl_seconds: i64,
+    #[serde(default = "default_resource")]
+    pub reason: String,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+fn default_hold_ttl_seconds() -> i64 {
+    3600
+}
+
+// 冻结用户部分余额的返回报文载荷
+#[derive(Serialize)]
+pub struct HoldCreditResponseData {
+    pub currency: String,
+    pub user_id: String,
+    pub hold_id: String,
+    pub expires_at: i64,
+    pub credit: i64,
+    pub held: i64,
+    pub available: i64,
+}
+
+// 将冻结款结算给收款方的报文载荷
+#[derive(Deserialize)]
+pub struct CaptureHoldRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub user_id: String,
+    pub hold_id: String,
     pub to_user_id: String,
-    pub to_user_credit: i32,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+// 将冻结款结算给收款方的返回报文载荷
+#[derive(Serialize)]
+pub struct CaptureHoldResponseData {
+    pub currency: String,
+    pub user_id: String,
+    pub user_credit: i64, // 结算后的余额
+    pub to_user_id: String,
+    pub to_user_credit: i64,
+    pub captured: i64, // 结算的金额
+    pub fee: i64,      // 付款方另外支付的手续费
+    pub transaction_id: String,
+}
+
+// 释放冻结款的报文载荷
+#[derive(Deserialize)]
+pub struct ReleaseHoldRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub user_id: String,
+    pub hold_id: String,
+}
+
+// 释放冻结款的返回报文载荷
+#[derive(Serialize)]
+pub struct ReleaseHoldResponseData {
+    pub currency: String,
+    pub user_id: String,
+    pub hold_id: String,
+    pub released: i64, // 释放的金额
+    pub available: i64,
+}
+
+// 冲正一笔交易的报文载荷
+#[derive(Deserialize)]
+pub struct ReverseTransactionRequestData {
+    pub transaction_id: String,
+    #[serde(default = "default_resource")]
+    pub reason: String,
+    // 允许冲正后余额为负, 例如追回已被花掉的诈骗所得
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+// 冲正一笔交易的返回报文载荷
+#[derive(Serialize)]
+pub struct ReverseTransactionResponseData {
+    pub currency: String,
+    pub transaction_id: String, // 冲正交易的id
+    pub reverses: String,       // 被冲正交易的id
+    pub entries: Vec<EcosystemTransactionEntry>,
+}
+
+// 创建定时支付的报文载荷
+#[derive(Deserialize)]
+pub struct SchedulePaymentRequestData {
+    pub action: EcosystemScheduledAction,
+    // 与 eco_alter_user_credit_request 或 eco_transfer_user_credit_request 相同的载荷
+    pub payload: serde_json::Value,
+    // 首次执行的时间(秒级时间戳), 不填为立即执行
+    pub run_at: Option<i64>,
+    // 不填为一次性任务
+    pub interval_seconds: Option<i64>,
+    pub max_runs: Option<u64>,
+}
+
+// 创建或取消定时支付的返回报文载荷
+#[derive(Serialize)]
+pub struct ScheduledPaymentResponseData {
+    pub job: EcosystemScheduledPayment,
+}
+
+// 列出定时支付的报文载荷
+#[derive(Deserialize)]
+pub struct ListScheduledPaymentsRequestData {
+    // 只列出该客户端创建的任务; 非管理员客户端总是只能看到自己创建的任务
+    pub created_by: Option<String>,
+}
+
+// 列出定时支付的返回报文载荷
+#[derive(Serialize)]
+pub struct ListScheduledPaymentsResponseData {
+    pub jobs: Vec<EcosystemScheduledPayment>,
+}
+
+// 取消定时支付的报文载荷
+#[derive(Deserialize)]
+pub struct CancelScheduledPaymentRequestData {
+    pub job_id: String,
+}
+
+// 订阅或取消订阅余额变动事件的报文载荷
+#[derive(Deserialize)]
+pub struct SubscriptionRequestData {
+    // 订阅时为订阅所有用户, 取消订阅时为取消对所有用户的订阅
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default)]
+    pub user_ids: Vec<String>,
+}
+
+// 订阅或取消订阅余额变动事件的返回报文载荷, 为本连接当前的订阅
+#[derive(Serialize)]
+pub struct SubscriptionResponseData {
+    pub all: bool,
+    pub user_ids: Vec<String>,
+}
+
+// 查询余额排行榜的报文载荷
+#[derive(Deserialize)]
+pub struct GetLeaderboardRequestData {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    #[serde(default = "default_leaderboard_top")]
+    pub top: usize,
+    // 同时查询该用户的名次
+    pub user_id: Option<String>,
+}
+
+fn default_leaderboard_top() -> usize {
+    10
+}
+
+// 排行榜中的一项, 名次从1开始
+#[derive(Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub user_id: String,
+    pub credit: i64,
+}
+
+// 查询余额排行榜的返回报文载荷
+#[derive(Serialize)]
+pub struct GetLeaderboardResponseData {
+    pub currency: String,
+    pub entries: Vec<LeaderboardEntry>,
+    pub user_entry: Option<LeaderboardEntry>, // 请求中的用户不在排行榜上时为null
+}
+
+// 重建余额排行榜的返回报文载荷
+#[derive(Serialize)]
+pub struct RebuildLeaderboardResponseData {
+    pub user_counts: HashMap<String, usize>, // 货币名 -> 排行榜中的用户数
 }
@@ -1,39 +1,125 @@
-use redis::RedisError;
 use serde::{Deserialize, Serialize};
 
 use self::common::CommonErrorResponseData;
+use crate::error::FineError;
 // websocket事件
+pub mod admin;
 pub mod common;
 pub mod ecosystem;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageType {
     #[serde(rename = "common_success_response")]
     CommonSuccessResponse, // 通用成功返回结构
     #[serde(rename = "common_error_response")]
     CommonErrorResponse, // 通用错误返回结构
+    #[serde(rename = "admin_list_connections_request")]
+    AdminListConnectionsRequest,
+    #[serde(rename = "admin_list_connections_response")]
+    AdminListConnectionsResponse,
+    #[serde(rename = "admin_send_to_server_request")]
+    AdminSendToServerRequest,
+    #[serde(rename = "admin_send_to_server_response")]
+    AdminSendToServerResponse,
+    #[serde(rename = "admin_server_notice")]
+    AdminServerNotice, // 由其他服务器或子系统转发给指定服务器的通知
     #[serde(rename = "eco_set_user_credit_request")]
     EcosytemSetUserCreditRequest,
     #[serde(rename = "eco_set_user_credit_response")]
     EcosytemSetUserCreditResponse,
+    #[serde(rename = "eco_create_account_request")]
+    EcosytemCreateAccountRequest,
+    #[serde(rename = "eco_create_account_response")]
+    EcosytemCreateAccountResponse,
+    #[serde(rename = "eco_freeze_account_request")]
+    EcosytemFreezeAccountRequest,
+    #[serde(rename = "eco_freeze_account_response")]
+    EcosytemFreezeAccountResponse,
+    #[serde(rename = "eco_unfreeze_account_request")]
+    EcosytemUnfreezeAccountRequest,
+    #[serde(rename = "eco_unfreeze_account_response")]
+    EcosytemUnfreezeAccountResponse,
+    #[serde(rename = "eco_close_account_request")]
+    EcosytemCloseAccountRequest,
+    #[serde(rename = "eco_close_account_response")]
+    EcosytemCloseAccountResponse,
     #[serde(rename = "eco_get_user_credit_request")]
     EcosytemGetUserCreditRequest,
     #[serde(rename = "eco_get_user_credit_response")]
     EcosytemGetUserCreditResponse,
+    #[serde(rename = "eco_get_user_credit_history_request")]
+    EcosytemGetUserCreditHistoryRequest,
+    #[serde(rename = "eco_get_user_credit_history_response")]
+    EcosytemGetUserCreditHistoryResponse,
     #[serde(rename = "eco_alter_user_credit_request")]
     EcosytemAlterUserCreditRequest,
     #[serde(rename = "eco_alter_user_credit_response")]
     EcosytemAlterUserCreditResponse,
+    #[serde(rename = "eco_batch_alter_request")]
+    EcosytemBatchAlterRequest,
+    #[serde(rename = "eco_batch_alter_response")]
+    EcosytemBatchAlterResponse,
     #[serde(rename = "eco_transfer_user_credit_request")]
     EcosytemTransferUserCreditRequest,
     #[serde(rename = "eco_transfer_user_credit_response")]
     EcosytemTransferUserCreditResponse,
+    #[serde(rename = "eco_hold_credit_request")]
+    EcosytemHoldCreditRequest,
+    #[serde(rename = "eco_hold_credit_response")]
+    EcosytemHoldCreditResponse,
+    #[serde(rename = "eco_capture_hold_request")]
+    EcosytemCaptureHoldRequest,
+    #[serde(rename = "eco_capture_hold_response")]
+    EcosytemCaptureHoldResponse,
+    #[serde(rename = "eco_release_hold_request")]
+    EcosytemReleaseHoldRequest,
+    #[serde(rename = "eco_release_hold_response")]
+    EcosytemReleaseHoldResponse,
+    #[serde(rename = "eco_reverse_transaction_request")]
+    EcosytemReverseTransactionRequest,
+    #[serde(rename = "eco_reverse_transaction_response")]
+    EcosytemReverseTransactionResponse,
+    #[serde(rename = "eco_schedule_payment_request")]
+    EcosytemSchedulePaymentRequest,
+    #[serde(rename = "eco_schedule_payment_response")]
+    EcosytemSchedulePaymentResponse,
+    #[serde(rename = "eco_list_scheduled_payments_request")]
+    EcosytemListScheduledPaymentsRequest,
+    #[serde(rename = "eco_list_scheduled_payments_response")]
+    EcosytemListScheduledPaymentsResponse,
+    #[serde(rename = "eco_cancel_scheduled_payment_request")]
+    EcosytemCancelScheduledPaymentRequest,
+    #[serde(rename = "eco_cancel_scheduled_payment_response")]
+    EcosytemCancelScheduledPaymentResponse,
+    #[serde(rename = "eco_subscribe_request")]
+    EcosytemSubscribeRequest,
+    #[serde(rename = "eco_subscribe_response")]
+    EcosytemSubscribeResponse,
+    #[serde(rename = "eco_unsubscribe_request")]
+    EcosytemUnsubscribeRequest,
+    #[serde(rename = "eco_unsubscribe_response")]
+    EcosytemUnsubscribeResponse,
+    #[serde(rename = "eco_credit_changed_event")]
+    EcosytemCreditChangedEvent, // 服务端主动推送, 载荷为 EcosystemCreditChangedEvent
+    #[serde(rename = "eco_get_leaderboard_request")]
+    EcosytemGetLeaderboardRequest,
+    #[serde(rename = "eco_get_leaderboard_response")]
+    EcosytemGetLeaderboardResponse,
+    #[serde(rename = "eco_rebuild_leaderboard_request")]
+    EcosytemRebuildLeaderboardRequest,
+    #[serde(rename = "eco_rebuild_leaderboard_response")]
+    EcosytemRebuildLeaderboardResponse,
     // ...
+    #[serde(other)]
+    Unknown, // 未知的报文类型
 }
 // 所有websockte事件的外层包裹
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub message_type: MessageType,
+    // 请求方自定义的关联id, 原样附带在对应的返回报文中
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
     pub data: serde_json::Value,
 }
 
@@ -41,19 +127,14 @@ impl From<CommonErrorResponseData> for Message {
     fn from(data: CommonErrorResponseData) -> Self {
         Message {
             message_type: MessageType::CommonErrorResponse,
+            request_id: None,
             data: serde_json::to_value(data).unwrap(),
         }
     }
 }
 
-impl From<RedisError> for Message {
-    fn from(err: RedisError) -> Self {
-        Message {
-            message_type: MessageType::CommonErrorResponse,
-            data: serde_json::to_value(CommonErrorResponseData {
-                message: err.to_string(),
-            })
-            .unwrap(),
-        }
+impl From<FineError> for Message {
+    fn from(err: FineError) -> Self {
+        Message::from(CommonErrorResponseData::from(err))
     }
 }
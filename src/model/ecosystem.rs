@@ -1,16 +1,268 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 // 经济系统中的一条记录
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EcosystemUserAccountRecord {
-    pub credit: i32,
-    pub alter_records: Vec<EcosystemUserCreditAlterRecord>,
+    pub credit: i64, // 总余额, 包含被冻结的部分
+    #[serde(default)]
+    pub status: EcosystemAccountStatus,
+    // 尚未结算的冻结款
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holds: Vec<EcosystemCreditHold>,
+}
+
+impl EcosystemUserAccountRecord {
+    pub fn new(credit: i64) -> Self {
+        EcosystemUserAccountRecord {
+            credit,
+            status: EcosystemAccountStatus::Active,
+            holds: vec![],
+        }
+    }
+
+    // 未过期的冻结款总额
+    pub fn held_credit(&self, now: i64) -> i64 {
+        self.holds
+            .iter()
+            .filter(|hold| hold.expires_at > now)
+            .map(|hold| hold.credit)
+            .sum()
+    }
+
+    // 可用余额, 即总余额减去冻结款
+    pub fn available_credit(&self, now: i64) -> i64 {
+        self.credit.saturating_sub(self.held_credit(now))
+    }
+
+    // 清理已过期的冻结款, 过期即视为已释放
+    pub fn prune_expired_holds(&mut self, now: i64) {
+        self.holds.retain(|hold| hold.expires_at > now);
+    }
+}
+
+// 一笔冻结款, 例如拍卖中的出价
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcosystemCreditHold {
+    pub hold_id: String,
+    pub credit: i64,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub reason: String,
+}
+
+// 账户状态
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EcosystemAccountStatus {
+    #[default]
+    Active,
+    Frozen, // 冻结: 不能转出或扣款, 仍可入账
+    Closed, // 注销: 不能进行任何资产变动
 }
 
 // 用户资产变动记录
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RawUserCreditAlterRecord")]
 pub struct EcosystemUserCreditAlterRecord {
     pub time: i64,
-    pub credit: i32,
-    pub reason: String,
+    pub credit: i64,
+    pub kind: EcosystemCreditChangeKind,
+    // 交易对方, 例如转账的另一方
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counterparty: Option<String>,
+    // 发起变动的客户端名
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_client: Option<String>,
+    // 调用方附带的自定义信息
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+    // 同一次操作产生的记录共用一个交易id, 旧记录没有交易id
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub transaction_id: String,
+    // 冲正记录指向被冲正的交易
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reverses: Option<String>,
+    // 直接设置余额前的余额, 冲正时据此恢复; 旧记录没有该字段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_credit: Option<i64>,
+}
+
+// 资产变动的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EcosystemCreditChangeKind {
+    TransferIn,
+    TransferOut,
+    AdminSet, // 直接设置余额, 记录中的金额为设置后的余额
+    Alter,
+    Fee,
+    Reward,
+    Reversal,
+}
+
+// 反序列化用的资产变动记录, 兼容只有 reason 字符串的旧格式
+#[derive(Deserialize)]
+struct RawUserCreditAlterRecord {
+    time: i64,
+    credit: i64,
+    kind: Option<EcosystemCreditChangeKind>,
+    #[serde(default)]
+    reason: String,
+    counterparty: Option<String>,
+    source_client: Option<String>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    #[serde(default)]
+    transaction_id: String,
+    reverses: Option<String>,
+    previous_credit: Option<i64>,
+}
+
+impl From<RawUserCreditAlterRecord> for EcosystemUserCreditAlterRecord {
+    fn from(raw: RawUserCreditAlterRecord) -> Self {
+        let (kind, counterparty, metadata) = match raw.kind {
+            Some(kind) => (kind, raw.counterparty, raw.metadata),
+            None => parse_legacy_reason(&raw.reason, raw.credit),
+        };
+        EcosystemUserCreditAlterRecord {
+            time: raw.time,
+            credit: raw.credit,
+            kind,
+            counterparty,
+            source_client: raw.source_client,
+            metadata,
+            transaction_id: raw.transaction_id,
+            reverses: raw.reverses,
+            previous_credit: raw.previous_credit,
+        }
+    }
 }
+
+// 解析旧格式的 reason, 旧版本只为转账写入固定格式:
+// `transfer$#$from:X$#$to:Y`(转出方), `transfer/from:X/to:Y`(转入方)
+// 其余 reason 是调用方传入的原文, 视为普通增减, 原文保存在 metadata 的 reason 中
+fn parse_legacy_reason(
+    reason: &str,
+    credit: i64,
+) -> (
+    EcosystemCreditChangeKind,
+    Option<String>,
+    HashMap<String, String>,
+) {
+    // 返回 (转出方, 转入方)
+    fn transfer<'a>(rest: &'a str, separator: &str) -> Option<(&'a str, &'a str)> {
+        let (from, to) = rest.split_once(&format!("{}to:", separator))?;
+        Some((from.strip_prefix("from:")?, to))
+    }
+    if let Some((_, to)) = reason
+        .strip_prefix("transfer$#$")
+        .and_then(|rest| transfer(rest, "$#$"))
+        .filter(|_| credit <= 0)
+    {
+        return (
+            EcosystemCreditChangeKind::TransferOut,
+            Some(to.to_string()),
+            HashMap::new(),
+        );
+    }
+    if let Some((from, _)) = reason
+        .strip_prefix("transfer/")
+        .and_then(|rest| transfer(rest, "/"))
+        .filter(|_| credit >= 0)
+    {
+        return (
+            EcosystemCreditChangeKind::TransferIn,
+            Some(from.to_string()),
+            HashMap::new(),
+        );
+    }
+    let mut metadata = HashMap::new();
+    if !reason.is_empty() {
+        metadata.insert("reason".to_string(), reason.to_string());
+    }
+    (EcosystemCreditChangeKind::Alter, None, metadata)
+}
+
+// 一次操作的交易记录, 汇总该操作在各账户上产生的资产变动
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcosystemTransactionRecord {
+    pub transaction_id: String,
+    pub currency: String,
+    pub time: i64,
+    pub entries: Vec<EcosystemTransactionEntry>,
+    // 本交易冲正的原交易
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverses: Option<String>,
+    // 冲正本交易的交易, 一笔交易只能被冲正一次
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reversed_by: Option<String>,
+}
+
+// 交易中单个账户的资产变动
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcosystemTransactionEntry {
+    pub user_id: String,
+    pub credit: i64,
+    pub kind: EcosystemCreditChangeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterparty: Option<String>,
+    // 直接设置余额前的余额, 含义同资产变动记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_credit: Option<i64>,
+}
+
+// 定时或周期执行的支付任务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcosystemScheduledPayment {
+    pub job_id: String,
+    pub action: EcosystemScheduledAction,
+    // 与对应请求报文相同的载荷, 执行时附带按任务与执行时间生成的幂等key
+    pub payload: serde_json::Value,
+    pub next_run_at: i64,
+    // 周期任务的间隔秒数, 一次性任务为None
+    pub interval_seconds: Option<i64>,
+    // 最多执行的次数, None 表示不限
+    pub max_runs: Option<u64>,
+    pub runs: u64,
+    pub created_by: String, // 创建任务的客户端名
+    pub created_at: i64,
+    pub last_run_at: Option<i64>,
+    pub last_error: Option<String>, // 上一次执行失败的原因
+    #[serde(default)]
+    pub status: EcosystemScheduledPaymentStatus,
+}
+
+// 定时任务的状态
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EcosystemScheduledPaymentStatus {
+    #[default]
+    Active,
+    // 最后一次执行失败的已结束任务, 不再执行, 保留一段时间供创建者查看, 取消即删除
+    Failed,
+}
+
+// 定时任务执行的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EcosystemScheduledAction {
+    Alter,
+    Transfer,
+}
+
+// 账户余额或状态变动的事件, 经 redis 发布到所有服务实例后推送给订阅的连接
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcosystemCreditChangedEvent {
+    pub currency: String,
+    pub user_id: String,
+    pub credit: i64, // 变动后的余额
+    pub delta: i64,
+    pub status: EcosystemAccountStatus,
+    pub transaction_id: Option<String>,
+    pub time: i64,
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,66 @@
+use serde_json::json;
+
+use super::*;
+
+// 旧版本写入账户 alter_records 中的记录只有 time, credit, reason 三个字段
+fn legacy_record(credit: i64, reason: &str) -> EcosystemUserCreditAlterRecord {
+    serde_json::from_value(json!({ "time": 1690000000, "credit": credit, "reason": reason }))
+        .unwrap()
+}
+
+#[test]
+fn legacy_transfer_out_reason() {
+    let record = legacy_record(-50, "transfer$#$from:10001$#$to:10002");
+    assert_eq!(record.kind, EcosystemCreditChangeKind::TransferOut);
+    assert_eq!(record.counterparty.as_deref(), Some("10002"));
+    assert!(record.metadata.is_empty());
+    assert!(record.transaction_id.is_empty());
+}
+
+#[test]
+fn legacy_transfer_in_reason() {
+    let record = legacy_record(50, "transfer/from:10001/to:10002");
+    assert_eq!(record.kind, EcosystemCreditChangeKind::TransferIn);
+    assert_eq!(record.counterparty.as_deref(), Some("10001"));
+    assert!(record.metadata.is_empty());
+}
+
+#[test]
+fn legacy_free_text_reason_is_kept_as_alter() {
+    // 设置与增减余额时 reason 为调用方传入的原文
+    for reason in [
+        "签到奖励",
+        "admin set",
+        "fee$#$from:10001$#$to:10002",
+        "reversal$#$transaction:abc$#$refund",
+        "transfer$#$from:10001",
+    ] {
+        let record = legacy_record(-10, reason);
+        assert_eq!(record.kind, EcosystemCreditChangeKind::Alter, "{}", reason);
+        assert_eq!(record.counterparty, None);
+        assert_eq!(
+            record.metadata.get("reason").map(String::as_str),
+            Some(reason)
+        );
+    }
+}
+
+#[test]
+fn legacy_empty_reason_has_no_metadata() {
+    let record = legacy_record(100, "");
+    assert_eq!(record.kind, EcosystemCreditChangeKind::Alter);
+    assert!(record.metadata.is_empty());
+}
+
+#[test]
+fn new_records_ignore_legacy_reason_parsing() {
+    let record: EcosystemUserCreditAlterRecord = serde_json::from_value(json!({
+        "time": 1690000000,
+        "credit": 10,
+        "kind": "reward",
+        "metadata": { "reason": "transfer/from:10001/to:10002" }
+    }))
+    .unwrap();
+    assert_eq!(record.kind, EcosystemCreditChangeKind::Reward);
+    assert_eq!(record.counterparty, None);
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Mutex, RwLock,
+    },
+};
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{info, log::warn};
+
+use crate::message::Message;
+
+// 一个已连接的游戏服务器
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub connection_id: u64,
+    pub server_name: String, // 连接时声明的服务器名, 未声明时为客户端名
+    pub version: Option<String>,
+    pub client_name: String, // 认证使用的客户端名
+    pub addr: SocketAddr,
+    pub connected_at: i64,
+    pub last_active_at: i64,
+}
+
+// 保留的最近连接事件数
+const MAX_RECENT_EVENTS: usize = 100;
+
+// 连接断开的原因
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    ClosedByPeer,
+    ReceiveError,
+    HeartbeatTimeout, // 连续多次ping没有回应, 通常是对方已崩溃的半开连接
+    IdleTimeout,
+}
+
+// 连接与断开事件
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionEvent {
+    pub connection_id: u64,
+    pub server_name: String,
+    pub time: i64,
+    pub event: ConnectionEventKind,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "reason")]
+pub enum ConnectionEventKind {
+    Connected,
+    Disconnected(DisconnectReason),
+}
+
+struct ConnectionEntry {
+    info: ConnectionInfo,
+    // 每收到一帧都会更新, 单独保存以免每次都获取写锁
+    last_active_at: AtomicI64,
+    // 该连接的写端, 用于向指定服务器发送报文
+    sender: mpsc::Sender<Message>,
+}
+
+impl ConnectionEntry {
+    fn snapshot(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            last_active_at: self.last_active_at.load(Ordering::Relaxed),
+            ..self.info.clone()
+        }
+    }
+}
+
+// 本实例上所有已连接的游戏服务器
+// 各实例各自维护, 不在实例间共享; 查询与发送通知都只涉及连接到本实例的服务器
+#[derive(Default)]
+pub struct ConnectionRegistry {
+    connections: RwLock<HashMap<u64, ConnectionEntry>>,
+    next_id: AtomicU64,
+    recent_events: Mutex<VecDeque<ConnectionEvent>>,
+}
+
+impl ConnectionRegistry {
+    // 登记一个新连接, 返回连接id
+    pub fn register(
+        &self,
+        server_name: String,
+        version: Option<String>,
+        client_name: String,
+        addr: SocketAddr,
+        sender: mpsc::Sender<Message>,
+    ) -> u64 {
+        let connection_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = chrono::Utc::now().timestamp();
+        let info = ConnectionInfo {
+            connection_id,
+            server_name,
+            version,
+            client_name,
+            addr,
+            connected_at: now,
+            last_active_at: now,
+        };
+        info!(
+            "Game server {} ({}) connected from {} as connection {}",
+            info.server_name, info.client_name, info.addr, connection_id
+        );
+        self.push_event(&info, ConnectionEventKind::Connected);
+        self.connections.write().unwrap().insert(
+            connection_id,
+            ConnectionEntry {
+                info,
+                last_active_at: AtomicI64::new(now),
+                sender,
+            },
+        );
+        connection_id
+    }
+
+    pub fn unregister(
+        &self,
+        connection_id: u64,
+        reason: DisconnectReason,
+    ) -> Option<ConnectionInfo> {
+        let info = self
+            .connections
+            .write()
+            .unwrap()
+            .remove(&connection_id)
+            .map(|entry| entry.snapshot())?;
+        info!(
+            "Game server {} disconnected from connection {}: {:?}",
+            info.server_name, connection_id, reason
+        );
+        self.push_event(&info, ConnectionEventKind::Disconnected(reason));
+        Some(info)
+    }
+
+    fn push_event(&self, info: &ConnectionInfo, event: ConnectionEventKind) {
+        let mut recent_events = self.recent_events.lock().unwrap();
+        if recent_events.len() == MAX_RECENT_EVENTS {
+            recent_events.pop_front();
+        }
+        recent_events.push_back(ConnectionEvent {
+            connection_id: info.connection_id,
+            server_name: info.server_name.clone(),
+            time: chrono::Utc::now().timestamp(),
+            event,
+        });
+    }
+
+    // 最近的连接与断开事件, 从旧到新排列
+    pub fn recent_events(&self) -> Vec<ConnectionEvent> {
+        self.recent_events.lock().unwrap().iter().cloned().collect()
+    }
+
+    // 记录连接的最近活动时间
+    pub fn touch(&self, connection_id: u64) {
+        if let Some(entry) = self.connections.read().unwrap().get(&connection_id) {
+            entry
+                .last_active_at
+                .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+        }
+    }
+
+    // 按连接时间排序的所有连接
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<ConnectionInfo> = self
+            .connections
+            .read()
+            .unwrap()
+            .values()
+            .map(ConnectionEntry::snapshot)
+            .collect();
+        connections.sort_by_key(|info| info.connection_id);
+        connections
+    }
+
+    // 向声明为 server_name 的所有连接发送报文, 返回成功送达的连接数与未能送达的连接id
+    // 不等待发送队列: 队列已满(对方处理过慢)或连接正在关闭时直接计为未送达
+    pub fn send_to_server(&self, server_name: &str, message: Message) -> (usize, Vec<u64>) {
+        let senders: Vec<(u64, mpsc::Sender<Message>)> = self
+            .connections
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.info.server_name == server_name)
+            .map(|(connection_id, entry)| (*connection_id, entry.sender.clone()))
+            .collect();
+        let mut delivered = 0;
+        let mut failed = vec![];
+        for (connection_id, sender) in senders {
+            match sender.try_send(message.clone()) {
+                Ok(()) => delivered += 1,
+                Err(err) => {
+                    warn!(
+                        "Failed to send to {} on connection {}: {}",
+                        server_name, connection_id, err
+                    );
+                    failed.push(connection_id);
+                }
+            }
+        }
+        failed.sort_unstable();
+        (delivered, failed)
+    }
+}
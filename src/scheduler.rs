@@ -0,0 +1,145 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use redis::aio::MultiplexedConnection;
+use tracing::{info, log::warn};
+
+use crate::{
+    auth::ClientIdentity,
+    error::FineError,
+    handler::{
+        ecosystem::{alter_user_credit_with_ttl, transfer_user_credit_with_ttl},
+        schedule::MAX_SCHEDULE_INTERVAL_SECONDS,
+    },
+    model::ecosystem::{
+        EcosystemScheduledAction, EcosystemScheduledPayment, EcosystemScheduledPaymentStatus,
+    },
+    storage, FineState,
+};
+
+// 每一轮最多执行的任务数, 剩余的到期任务留到下一轮
+const SCHEDULER_BATCH_SIZE: isize = 100;
+
+// 定时执行的幂等记录保留时间, 不短于最长的执行间隔
+// 执行成功但推进任务失败时(例如 redis 长时间不可用), 在此期限内恢复后重试都不会重复执行
+const SCHEDULED_IDEMPOTENCY_TTL_SECONDS: usize = MAX_SCHEDULE_INTERVAL_SECONDS as usize;
+
+// 定时支付执行器, 每隔 poll_interval 执行一轮到期的任务
+// 每次执行都带有由任务id与本次执行时间组成的幂等key, 重启或多实例同时执行时同一次执行只会生效一次
+pub async fn run_scheduler(fine_state: Arc<FineState>, poll_interval: Duration) {
+    let pool = &fine_state.redis_pool;
+    let mut ticker = tokio::time::interval(poll_interval);
+    loop {
+        ticker.tick().await;
+        // redis 不可用时跳过本轮, 到期任务留到下一轮执行
+        let mut redis_conn = match pool.get().await {
+            Ok(redis_conn) => redis_conn,
+            Err(err) => {
+                warn!("Failed to run scheduled payments: {}", err);
+                continue;
+            }
+        };
+        if let Err(err) = run_due_payments(redis_conn.conn(), &fine_state).await {
+            if err.is_transient() {
+                pool.invalidate(&redis_conn).await;
+            }
+            warn!("Failed to run scheduled payments: {}", err);
+        }
+    }
+}
+
+async fn run_due_payments(
+    redis_conn: &mut MultiplexedConnection,
+    fine_state: &FineState,
+) -> Result<(), FineError> {
+    let now = chrono::Utc::now().timestamp();
+    for job_id in storage::schedule::due_job_ids(redis_conn, now, SCHEDULER_BATCH_SIZE).await? {
+        match storage::schedule::get_job(redis_conn, &job_id).await? {
+            Some((raw, job)) if job.next_run_at <= now => {
+                run_job(redis_conn, fine_state, &raw, job, now).await?
+            }
+            Some(_) => {}
+            // 到期索引中残留的已删除任务
+            None => {
+                storage::schedule::replace_job(redis_conn, &job_id, None, None).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// 执行任务的一次到期, 并推进到下一次执行时间, 删除已结束的任务或将其标记为失败
+async fn run_job(
+    redis_conn: &mut MultiplexedConnection,
+    fine_state: &FineState,
+    raw: &str,
+    mut job: EcosystemScheduledPayment,
+    now: i64,
+) -> Result<(), FineError> {
+    let occurrence = job.next_run_at;
+    let mut payload = job.payload.clone();
+    if let Some(payload) = payload.as_object_mut() {
+        payload.insert(
+            "idempotency_key".to_string(),
+            format!("schedule:{}:{}", job.job_id, occurrence).into(),
+        );
+    }
+    // 以创建者的名义执行, 权限与限额已在创建任务时校验
+    let identity = ClientIdentity {
+        name: job.created_by.clone(),
+        scopes: HashSet::new(),
+        alter_limit: None,
+    };
+    let config = &fine_state.economy_config;
+    let result = match job.action {
+        EcosystemScheduledAction::Alter => {
+            alter_user_credit_with_ttl(
+                payload,
+                redis_conn,
+                config,
+                &identity,
+                SCHEDULED_IDEMPOTENCY_TTL_SECONDS,
+            )
+            .await
+        }
+        EcosystemScheduledAction::Transfer => {
+            transfer_user_credit_with_ttl(
+                payload,
+                redis_conn,
+                config,
+                &identity,
+                SCHEDULED_IDEMPOTENCY_TTL_SECONDS,
+            )
+            .await
+        }
+    };
+    match result {
+        // 存储出错时不推进任务, 下一轮以相同的幂等key重试本次执行
+        Err(err @ (FineError::Storage(_) | FineError::TooManyConflicts)) => return Err(err),
+        Err(err) => {
+            warn!("Scheduled payment {} failed: {}", job.job_id, err);
+            job.last_error = Some(err.to_string());
+        }
+        Ok(_) => {
+            info!("Scheduled payment {} executed", job.job_id);
+            job.last_error = None;
+        }
+    }
+    job.runs += 1;
+    job.last_run_at = Some(now);
+    let finished = match job.interval_seconds {
+        Some(interval) => {
+            job.next_run_at = occurrence + interval;
+            job.max_runs.is_some_and(|max_runs| job.runs >= max_runs)
+        }
+        None => true,
+    };
+    // 最后一次执行失败的任务保留为失败状态, 创建者可以列出并查看失败原因
+    if finished && job.last_error.is_some() {
+        job.status = EcosystemScheduledPaymentStatus::Failed;
+    }
+    let keep = !finished || job.status == EcosystemScheduledPaymentStatus::Failed;
+    // 写入失败说明任务已被取消或由其他实例推进
+    storage::schedule::replace_job(redis_conn, &job.job_id, Some(raw), keep.then_some(&job))
+        .await?;
+    Ok(())
+}
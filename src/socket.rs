@@ -1,19 +1,44 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        ConnectInfo, State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Query, State,
     },
+    http::HeaderMap,
     response::Response,
 };
+use futures_util::{SinkExt, StreamExt};
+use redis::aio::MultiplexedConnection;
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc, Semaphore},
+    time::{interval_at, Instant},
+};
 use tracing::{info, log::warn};
 
 use crate::{
-    handler::ecosystem::{
-        alter_user_credit, get_user_credit, set_user_credit, transfer_user_credit,
+    auth::{self, ClientIdentity, Scope},
+    error::FineError,
+    events::Subscription,
+    handler::{
+        admin,
+        ecosystem::{
+            alter_user_credit, batch_alter_user_credit, capture_hold, close_account,
+            create_account, freeze_account, get_leaderboard, get_user_credit,
+            get_user_credit_history, hold_credit, rebuild_leaderboard, release_hold,
+            reverse_transaction, set_user_credit, transfer_user_credit, unfreeze_account,
+        },
+        schedule, subscription,
     },
-    message::{self, common::CommonErrorResponseData, MessageType},
+    message::{self, MessageType},
+    registry::DisconnectReason,
+    storage::pool::RetryConfig,
     FineState,
 };
 
@@ -21,59 +46,438 @@ pub async fn socket_upgrader(
     ws: WebSocketUpgrade,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(fine_state): State<Arc<FineState>>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
 ) -> Response {
     info!("New WebSocket connection from {}", addr);
-    ws.on_upgrade(|socket| socket_handler(socket, fine_state))
+    let identity = auth::extract_token(&headers, &query)
+        .and_then(|token| fine_state.client_credentials.get(&token).cloned());
+    match identity {
+        Some(identity) => {
+            info!(
+                "Game server {} authenticated from {} with scopes {:?}",
+                identity.name, addr, identity.scopes
+            );
+            // 服务器在连接时通过 server_name 与 version 参数声明自己, 未声明时使用客户端名
+            let server_name = query
+                .get("server_name")
+                .cloned()
+                .unwrap_or_else(|| identity.name.clone());
+            let version = query.get("version").cloned();
+            ws.on_upgrade(move |socket| {
+                socket_handler(socket, fine_state, identity, addr, server_name, version)
+            })
+        }
+        None => {
+            warn!(
+                "Rejected unauthenticated WebSocket connection from {}",
+                addr
+            );
+            ws.on_upgrade(reject_socket)
+        }
+    }
 }
 
-pub async fn socket_handler(mut socket: WebSocket, fine_state: Arc<FineState>) {
-    let mut redis_conn = fine_state
-        .redis_client
-        .get_async_connection()
+// 告知客户端认证失败后关闭连接
+async fn reject_socket(mut socket: WebSocket) {
+    let resp = message::Message::from(FineError::Unauthorized);
+    socket
+        .send(Message::Text(serde_json::to_string(&resp).unwrap()))
         .await
-        .unwrap();
-    while let Some(msg) = socket.recv().await {
-        if let Ok(msg) = msg {
-            if let Message::Text(msg) = msg {
-                // 对消息进行初步反序列化
-                let msg_recv: Result<message::Message, serde_json::Error> =
-                    serde_json::from_str(&msg);
-                let resp: message::Message;
-                if msg_recv.is_err() {
-                    resp = message::Message::from(CommonErrorResponseData {
-                        message: "invalid message".to_string(),
-                    });
-                } else {
-                    let msg = msg_recv.unwrap();
-                    match msg.message_type {
-                        MessageType::EcosytemSetUserCreditRequest => {
-                            resp = set_user_credit(msg.data, &mut redis_conn).await;
-                        }
-                        MessageType::EcosytemGetUserCreditRequest => {
-                            resp = get_user_credit(msg.data, &mut redis_conn).await;
-                        }
-                        MessageType::EcosytemAlterUserCreditRequest => {
-                            resp = alter_user_credit(msg.data, &mut redis_conn).await;
-                        }
-                        MessageType::EcosytemTransferUserCreditRequest => {
-                            resp = transfer_user_credit(msg.data, &mut redis_conn).await;
-                        }
-                        _ => todo!(),
+        .ok();
+    socket
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: Cow::from("unauthorized"),
+        })))
+        .await
+        .ok();
+}
+
+// websocket心跳配置
+pub struct HeartbeatConfig {
+    // 服务端发送ping的间隔
+    pub ping_interval: Duration,
+    // 连续这么多次ping没有收到任何回应时判定连接已断开
+    pub max_missed_pongs: u32,
+    // 超过这么久没有收到请求时关闭连接, None 表示不限制
+    pub idle_timeout: Option<Duration>,
+}
+
+pub async fn socket_handler(
+    socket: WebSocket,
+    fine_state: Arc<FineState>,
+    identity: ClientIdentity,
+    addr: SocketAddr,
+    server_name: String,
+    version: Option<String>,
+) {
+    let identity = Arc::new(identity);
+    let max_in_flight = fine_state.socket_max_in_flight;
+    let (mut sender, mut receiver) = socket.split();
+
+    // 写端: 各请求任务处理完后把返回报文交给这里统一发送, 心跳与关闭帧走单独的通道
+    let (resp_tx, mut resp_rx) = mpsc::channel::<message::Message>(max_in_flight);
+    let (control_tx, mut control_rx) = mpsc::channel::<Message>(1);
+    let writer = tokio::spawn(async move {
+        loop {
+            let frame = tokio::select! {
+                resp = resp_rx.recv() => match resp {
+                    Some(resp) => Message::Text(serde_json::to_string(&resp).unwrap()),
+                    None => return,
+                },
+                Some(frame) = control_rx.recv() => frame,
+            };
+            if sender.send(frame).await.is_err() {
+                warn!("Failed to send message");
+                return;
+            }
+        }
+    });
+
+    let connection_id = fine_state.connections.register(
+        server_name,
+        version,
+        identity.name.clone(),
+        addr,
+        resp_tx.clone(),
+    );
+
+    // 推送端: 把本连接订阅的余额变动事件交给写端
+    let subscription = Arc::new(Mutex::new(Subscription::default()));
+    let mut events = fine_state.credit_events.subscribe();
+    let forwarder = {
+        let subscription = subscription.clone();
+        let resp_tx = resp_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Dropped {} credit change events", skipped);
+                        continue;
                     }
+                    Err(RecvError::Closed) => return,
+                };
+                if !subscription.lock().unwrap().matches(&event.user_id) {
+                    continue;
                 }
+                let msg = message::Message {
+                    message_type: MessageType::EcosytemCreditChangedEvent,
+                    request_id: None,
+                    data: serde_json::to_value(&*event).unwrap(),
+                };
+                if resp_tx.send(msg).await.is_err() {
+                    return;
+                }
+            }
+        })
+    };
 
-                if socket
-                    .send(Message::Text(serde_json::to_string(&resp).unwrap()))
-                    .await
-                    .is_err()
+    // 读端: 每个请求放到单独的任务中并发处理, 同时处理的请求数受 max_in_flight 限制
+    // 同时定期发送ping, 收到任何帧都视为连接存活
+    let heartbeat_config = &fine_state.socket_heartbeat;
+    let mut heartbeat = interval_at(
+        Instant::now() + heartbeat_config.ping_interval,
+        heartbeat_config.ping_interval,
+    );
+    let mut missed_pongs = 0;
+    let mut last_request_at = Instant::now();
+    let in_flight = Arc::new(Semaphore::new(max_in_flight));
+    let reason = loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => msg,
+            _ = heartbeat.tick() => {
+                if missed_pongs >= heartbeat_config.max_missed_pongs {
+                    break DisconnectReason::HeartbeatTimeout;
+                }
+                if heartbeat_config
+                    .idle_timeout
+                    .is_some_and(|timeout| last_request_at.elapsed() >= timeout)
                 {
-                    warn!("Failed to send message");
-                    return;
+                    break DisconnectReason::IdleTimeout;
                 }
+                missed_pongs += 1;
+                control_tx.send(Message::Ping(vec![])).await.ok();
+                continue;
             }
-        } else {
-            warn!("Failed to receive message: {:?}", msg.unwrap_err());
-            return;
         };
+        let msg = match msg {
+            Some(Ok(msg)) => msg,
+            Some(Err(err)) => {
+                warn!("Failed to receive message: {:?}", err);
+                break DisconnectReason::ReceiveError;
+            }
+            None => break DisconnectReason::ClosedByPeer,
+        };
+        missed_pongs = 0;
+        fine_state.connections.touch(connection_id);
+        if let Message::Close(_) = msg {
+            break DisconnectReason::ClosedByPeer;
+        }
+        if let Message::Text(msg) = msg {
+            last_request_at = Instant::now();
+            let permit = in_flight.clone().acquire_owned().await.unwrap();
+            let resp_tx = resp_tx.clone();
+            let fine_state = fine_state.clone();
+            let identity = identity.clone();
+            let subscription = subscription.clone();
+            tokio::spawn(async move {
+                let resp = handle_message(&msg, &fine_state, &identity, &subscription).await;
+                drop(permit);
+                // 写端已退出时丢弃返回
+                resp_tx.send(resp).await.ok();
+            });
+        }
+    };
+    // 超时断开时告知对方原因
+    if let Some(close_reason) = match reason {
+        DisconnectReason::HeartbeatTimeout => Some("heartbeat timeout"),
+        DisconnectReason::IdleTimeout => Some("idle timeout"),
+        _ => None,
+    } {
+        control_tx
+            .send(Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: Cow::from(close_reason),
+            })))
+            .await
+            .ok();
+    }
+    // 等待仍在处理中的请求发送完返回后再关闭连接
+    fine_state.connections.unregister(connection_id, reason);
+    forwarder.abort();
+    drop(resp_tx);
+    drop(control_tx);
+    writer.await.ok();
+}
+
+// 各请求类型所需的权限, 非请求类型返回None
+fn required_scope(message_type: &MessageType) -> Option<Scope> {
+    match message_type {
+        MessageType::AdminListConnectionsRequest => Some(Scope::Admin),
+        MessageType::AdminSendToServerRequest => Some(Scope::Admin),
+        MessageType::EcosytemSetUserCreditRequest => Some(Scope::EcoSet),
+        MessageType::EcosytemCreateAccountRequest => Some(Scope::EcoAccount),
+        MessageType::EcosytemFreezeAccountRequest => Some(Scope::EcoAccount),
+        MessageType::EcosytemUnfreezeAccountRequest => Some(Scope::EcoAccount),
+        MessageType::EcosytemCloseAccountRequest => Some(Scope::EcoAccount),
+        MessageType::EcosytemGetUserCreditRequest => Some(Scope::EcoRead),
+        MessageType::EcosytemGetUserCreditHistoryRequest => Some(Scope::EcoRead),
+        MessageType::EcosytemAlterUserCreditRequest => Some(Scope::EcoAlter),
+        MessageType::EcosytemBatchAlterRequest => Some(Scope::EcoAlter),
+        MessageType::EcosytemTransferUserCreditRequest => Some(Scope::EcoTransfer),
+        MessageType::EcosytemHoldCreditRequest => Some(Scope::EcoHold),
+        MessageType::EcosytemCaptureHoldRequest => Some(Scope::EcoHold),
+        MessageType::EcosytemReleaseHoldRequest => Some(Scope::EcoHold),
+        MessageType::EcosytemReverseTransactionRequest => Some(Scope::EcoReverse),
+        MessageType::EcosytemSchedulePaymentRequest => Some(Scope::EcoSchedule),
+        MessageType::EcosytemListScheduledPaymentsRequest => Some(Scope::EcoSchedule),
+        MessageType::EcosytemCancelScheduledPaymentRequest => Some(Scope::EcoSchedule),
+        MessageType::EcosytemSubscribeRequest => Some(Scope::EcoRead),
+        MessageType::EcosytemUnsubscribeRequest => Some(Scope::EcoRead),
+        MessageType::EcosytemGetLeaderboardRequest => Some(Scope::EcoRead),
+        MessageType::EcosytemRebuildLeaderboardRequest => Some(Scope::Admin),
+        _ => None,
+    }
+}
+
+// 处理一条文本报文, 返回需要回复的报文
+async fn handle_message(
+    msg: &str,
+    fine_state: &FineState,
+    identity: &ClientIdentity,
+    subscription: &Mutex<Subscription>,
+) -> message::Message {
+    // 对消息进行初步反序列化
+    let msg_recv: Result<message::Message, serde_json::Error> = serde_json::from_str(msg);
+    let mut resp: message::Message;
+    match msg_recv {
+        Err(err) => {
+            resp = message::Message::from(FineError::InvalidMessage(err));
+            // 报文结构不合法时也尽量带回请求方的关联id
+            resp.request_id = serde_json::from_str::<serde_json::Value>(msg)
+                .ok()
+                .and_then(|v| v.get("request_id")?.as_str().map(str::to_string));
+        }
+        Ok(msg) => {
+            let r = match required_scope(&msg.message_type) {
+                None => Err(FineError::UnknownMessageType),
+                Some(scope) if !identity.has_scope(scope) => Err(FineError::Forbidden(format!(
+                    "missing scope {}",
+                    scope.as_str()
+                ))),
+                // 不访问存储的请求
+                Some(_) => match msg.message_type {
+                    MessageType::AdminListConnectionsRequest => {
+                        admin::list_connections(&fine_state.connections)
+                    }
+                    MessageType::AdminSendToServerRequest => {
+                        admin::send_to_server(msg.data, &fine_state.connections)
+                    }
+                    MessageType::EcosytemSubscribeRequest => {
+                        subscription::subscribe(msg.data, subscription)
+                    }
+                    MessageType::EcosytemUnsubscribeRequest => {
+                        subscription::unsubscribe(msg.data, subscription)
+                    }
+                    message_type => {
+                        let retry_safe = is_retry_safe(&message_type, &msg.data);
+                        handle_storage_message(
+                            &message_type,
+                            msg.data,
+                            retry_safe,
+                            fine_state,
+                            identity,
+                        )
+                        .await
+                    }
+                },
+            };
+            resp = r.unwrap_or_else(message::Message::from);
+            resp.request_id = msg.request_id;
+        }
+    }
+    resp
+}
+
+// 出现连接错误后能否安全地重放请求: 只读请求总是可以,
+// 写请求只有处理时会检查幂等key且请求带有幂等key时才可以
+fn is_retry_safe(message_type: &MessageType, data: &serde_json::Value) -> bool {
+    match message_type {
+        MessageType::EcosytemGetUserCreditRequest
+        | MessageType::EcosytemGetUserCreditHistoryRequest
+        | MessageType::EcosytemGetLeaderboardRequest
+        | MessageType::EcosytemListScheduledPaymentsRequest => true,
+        MessageType::EcosytemSetUserCreditRequest
+        | MessageType::EcosytemCreateAccountRequest
+        | MessageType::EcosytemAlterUserCreditRequest
+        | MessageType::EcosytemBatchAlterRequest
+        | MessageType::EcosytemTransferUserCreditRequest
+        | MessageType::EcosytemHoldCreditRequest
+        | MessageType::EcosytemCaptureHoldRequest
+        | MessageType::EcosytemReverseTransactionRequest => data
+            .get("idempotency_key")
+            .is_some_and(|key| key.is_string()),
+        _ => false,
     }
 }
+
+// 从连接池取连接处理需要访问存储的请求
+// 出现连接错误时丢弃该连接, 可以安全重放的请求带退避地重试, 最终失败时返回 StorageUnavailable;
+// 不能安全重放的请求可能已经生效, 返回 OutcomeUnknown 而不是提示重试
+async fn handle_storage_message(
+    message_type: &MessageType,
+    data: serde_json::Value,
+    retry_safe: bool,
+    fine_state: &FineState,
+    identity: &ClientIdentity,
+) -> Result<message::Message, FineError> {
+    let pool = &fine_state.redis_pool;
+    let mut attempt = 0;
+    loop {
+        let mut redis_conn = pool.get().await?;
+        let r = dispatch_storage_message(
+            message_type,
+            data.clone(),
+            redis_conn.conn(),
+            fine_state,
+            identity,
+        )
+        .await;
+        match r {
+            Err(err) if err.is_transient() => {
+                pool.invalidate(&redis_conn).await;
+                let delay = match retry_delay(retry_safe, attempt, &pool.retry) {
+                    Ok(delay) => delay,
+                    Err(give_up) => {
+                        warn!("Storage request failed: {}", err);
+                        return Err(give_up);
+                    }
+                };
+                warn!("Storage request failed, retrying in {:?}: {}", delay, err);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            r => return r,
+        }
+    }
+}
+
+// 请求发出后出现连接错误时, 返回重试前的等待时间, 或不再重试时返回给客户端的错误
+fn retry_delay(retry_safe: bool, attempt: u32, retry: &RetryConfig) -> Result<Duration, FineError> {
+    if !retry_safe {
+        return Err(FineError::OutcomeUnknown);
+    }
+    if attempt >= retry.max_retries {
+        return Err(FineError::StorageUnavailable);
+    }
+    Ok(retry.backoff(attempt))
+}
+
+async fn dispatch_storage_message(
+    message_type: &MessageType,
+    data: serde_json::Value,
+    redis_conn: &mut MultiplexedConnection,
+    fine_state: &FineState,
+    identity: &ClientIdentity,
+) -> Result<message::Message, FineError> {
+    let config = &fine_state.economy_config;
+    match message_type {
+        MessageType::EcosytemSetUserCreditRequest => {
+            set_user_credit(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemCreateAccountRequest => {
+            create_account(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemFreezeAccountRequest => freeze_account(data, redis_conn, config).await,
+        MessageType::EcosytemUnfreezeAccountRequest => {
+            unfreeze_account(data, redis_conn, config).await
+        }
+        MessageType::EcosytemCloseAccountRequest => close_account(data, redis_conn, config).await,
+        MessageType::EcosytemGetUserCreditRequest => {
+            get_user_credit(data, redis_conn, config).await
+        }
+        MessageType::EcosytemGetUserCreditHistoryRequest => {
+            get_user_credit_history(data, redis_conn, config).await
+        }
+        MessageType::EcosytemAlterUserCreditRequest => {
+            alter_user_credit(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemBatchAlterRequest => {
+            batch_alter_user_credit(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemTransferUserCreditRequest => {
+            transfer_user_credit(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemHoldCreditRequest => {
+            hold_credit(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemCaptureHoldRequest => {
+            capture_hold(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemReleaseHoldRequest => release_hold(data, redis_conn, config).await,
+        MessageType::EcosytemReverseTransactionRequest => {
+            reverse_transaction(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemSchedulePaymentRequest => {
+            schedule::schedule_payment(data, redis_conn, config, identity).await
+        }
+        MessageType::EcosytemListScheduledPaymentsRequest => {
+            schedule::list_scheduled_payments(data, redis_conn, identity).await
+        }
+        MessageType::EcosytemCancelScheduledPaymentRequest => {
+            schedule::cancel_scheduled_payment(data, redis_conn, identity).await
+        }
+        MessageType::EcosytemGetLeaderboardRequest => {
+            get_leaderboard(data, redis_conn, config).await
+        }
+        MessageType::EcosytemRebuildLeaderboardRequest => {
+            rebuild_leaderboard(redis_conn, config).await
+        }
+        _ => Err(FineError::UnknownMessageType),
+    }
+}
+
+#[cfg(test)]
+mod tests;
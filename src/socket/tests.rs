@@ -0,0 +1,53 @@
+use serde_json::json;
+
+use super::*;
+
+fn retry_config() -> RetryConfig {
+    RetryConfig {
+        max_retries: 3,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(2),
+    }
+}
+
+#[test]
+fn write_without_idempotency_key_reports_unknown_outcome() {
+    let data = json!({ "from_user_id": "alice", "to_user_id": "bob", "credit": 10 });
+    let retry_safe = is_retry_safe(&MessageType::EcosytemTransferUserCreditRequest, &data);
+    assert!(!retry_safe);
+    let err = retry_delay(retry_safe, 0, &retry_config()).unwrap_err();
+    assert_eq!(err.code(), "outcome_unknown");
+}
+
+#[test]
+fn idempotency_key_must_be_a_string() {
+    for key in [json!(null), json!(42)] {
+        let data = json!({ "user_id": "alice", "credit": 10, "idempotency_key": key });
+        assert!(!is_retry_safe(
+            &MessageType::EcosytemAlterUserCreditRequest,
+            &data
+        ));
+    }
+}
+
+#[test]
+fn retry_safe_request_retries_then_reports_unavailable() {
+    let data = json!({ "user_id": "alice", "credit": 10, "idempotency_key": "k1" });
+    let retry_safe = is_retry_safe(&MessageType::EcosytemAlterUserCreditRequest, &data);
+    assert!(retry_safe);
+    let retry = retry_config();
+    assert_eq!(
+        retry_delay(retry_safe, 0, &retry).unwrap(),
+        Duration::from_millis(100)
+    );
+    let err = retry_delay(retry_safe, 3, &retry).unwrap_err();
+    assert_eq!(err.code(), "storage_unavailable");
+}
+
+#[test]
+fn reads_are_always_retry_safe() {
+    assert!(is_retry_safe(
+        &MessageType::EcosytemGetUserCreditRequest,
+        &json!({ "user_id": "alice" })
+    ));
+}
@@ -0,0 +1,701 @@
+use std::time::Duration;
+
+use redis::{aio::MultiplexedConnection, AsyncCommands, Script};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use tracing::log::warn;
+
+use crate::{
+    auth::ClientIdentity,
+    config::DEFAULT_CURRENCY,
+    error::FineError,
+    events::CREDIT_CHANGED_CHANNEL,
+    model::ecosystem::{
+        EcosystemAccountStatus, EcosystemCreditChangeKind, EcosystemCreditChangedEvent,
+        EcosystemTransactionEntry, EcosystemTransactionRecord, EcosystemUserAccountRecord,
+        EcosystemUserCreditAlterRecord,
+    },
+};
+
+// 乐观并发冲突时的最大重试次数
+pub const MAX_UPDATE_RETRIES: u32 = 32;
+
+// 冲突后重试前随机等待, 等待上限从 CONFLICT_BACKOFF_BASE 开始每次翻倍, 最多 CONFLICT_BACKOFF_MAX
+// 避免多个请求争抢同一个热点账户(例如收取手续费的系统账户)时同时重试、反复冲突
+// 热点账户上的写入实际仍是逐笔进行, 持续高并发下可能重试耗尽而返回 too_many_conflicts
+const CONFLICT_BACKOFF_BASE: Duration = Duration::from_millis(2);
+const CONFLICT_BACKOFF_MAX: Duration = Duration::from_millis(100);
+
+// 幂等记录默认的保留时间
+pub const IDEMPOTENCY_KEY_TTL_SECONDS: usize = 24 * 60 * 60;
+
+// 分页读取资产变动记录时每次从redis取出的条数
+const HISTORY_SCAN_CHUNK: usize = 100;
+// 分页读取时每次最多检查 limit 的多少倍条记录, 过滤条件很少命中时分多次请求读完, 避免一次扫描全部记录
+const HISTORY_SCAN_FACTOR: usize = 20;
+
+// 比较并写入: 只有当所有账户与交易记录的当前值都与读取时一致, 才会一次性写入全部新值,
+// 同步排行榜并追加资产变动记录
+// KEYS[1..n]: 账户key, KEYS[n + 1..2n]: 对应的资产变动记录列表, KEYS[2n + 1]: 排行榜,
+// KEYS[2n + 2..2n + m + 1]: 交易记录key, KEYS[2n + m + 2]: 幂等key(可选)
+// ARGV[1]: 账户数量n, ARGV[2]: 交易记录数量m, ARGV[3]: 幂等key的过期秒数, ARGV[4]: 是否携带幂等key
+// 之后每个账户依次为: 读取时的旧值(不存在为空串), 新值(为空串时不写入), 用户id,
+// 新余额(为空串时从排行榜移除), 追加记录数c, c条记录
+// 之后每条交易记录依次为: 读取时的旧值(不存在为空串), 新值
+// 最后为幂等key的值(可选)
+const COMPARE_AND_SET_SCRIPT: &str = r#"
+local n = tonumber(ARGV[1])
+local m = tonumber(ARGV[2])
+local has_idempotency_key = ARGV[4] == '1'
+if has_idempotency_key and redis.call('EXISTS', KEYS[2 * n + m + 2]) == 1 then
+    return 0
+end
+local function unchanged(key, expected)
+    local current = redis.call('GET', key)
+    if current == false then
+        current = ''
+    end
+    return current == expected
+end
+local cursor = 5
+local updates = {}
+for i = 1, n do
+    if not unchanged(KEYS[i], ARGV[cursor]) then
+        return 0
+    end
+    local count = tonumber(ARGV[cursor + 4])
+    updates[i] = { cursor, count }
+    cursor = cursor + 5 + count
+end
+for i = 1, m do
+    if not unchanged(KEYS[2 * n + 1 + i], ARGV[cursor + 2 * (i - 1)]) then
+        return 0
+    end
+end
+for i = 1, n do
+    local first, count = updates[i][1], updates[i][2]
+    if ARGV[first + 1] ~= '' then
+        redis.call('SET', KEYS[i], ARGV[first + 1])
+        if ARGV[first + 3] == '' then
+            redis.call('ZREM', KEYS[2 * n + 1], ARGV[first + 2])
+        else
+            redis.call('ZADD', KEYS[2 * n + 1], ARGV[first + 3], ARGV[first + 2])
+        end
+    end
+    for j = first + 5, first + 4 + count do
+        redis.call('RPUSH', KEYS[n + i], ARGV[j])
+    end
+end
+for i = 1, m do
+    redis.call('SET', KEYS[2 * n + 1 + i], ARGV[cursor + 1])
+    cursor = cursor + 2
+end
+if has_idempotency_key then
+    redis.call('SET', KEYS[2 * n + m + 2], ARGV[cursor], 'EX', ARGV[3])
+end
+return 1
+"#;
+
+// 按账户的当前余额修正排行榜中的一个用户, 账户不存在或已注销时从排行榜移除
+// KEYS[1]: 账户key, KEYS[2]: 排行榜; ARGV[1]: 用户id
+const SYNC_LEADERBOARD_SCRIPT: &str = r#"
+local raw = redis.call('GET', KEYS[1])
+if raw == false then
+    return redis.call('ZREM', KEYS[2], ARGV[1])
+end
+local record = cjson.decode(raw)
+if record.status == 'closed' then
+    return redis.call('ZREM', KEYS[2], ARGV[1])
+end
+return redis.call('ZADD', KEYS[2], record.credit, ARGV[1])
+"#;
+
+// redis中保存的账户记录, 兼容资产变动记录仍内嵌在账户中的旧格式
+#[derive(Deserialize)]
+struct StoredAccountRecord {
+    #[serde(flatten)]
+    account: EcosystemUserAccountRecord,
+    alter_records: Option<Vec<EcosystemUserCreditAlterRecord>>,
+}
+
+// 分页读取资产变动记录时的过滤条件
+pub struct HistoryFilter<'a> {
+    // 闭区间的时间范围
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    // 资产变动的类型
+    pub kind: Option<EcosystemCreditChangeKind>,
+    // 交易对方
+    pub counterparty: Option<&'a str>,
+    // 变动原因(metadata.reason)的前缀
+    pub reason_prefix: Option<&'a str>,
+}
+
+// 一次原子修改中的单个账户
+pub struct AccountUpdate {
+    // 账户的当前记录, 不存在为None; 修改后为Some的记录会被写回
+    pub account: Option<EcosystemUserAccountRecord>,
+    // 本次修改需要追加的资产变动记录
+    pub new_records: Vec<EcosystemUserCreditAlterRecord>,
+}
+
+// 默认货币沿用单货币时期的key, 其他货币的key中带有货币名
+fn currency_key_prefix(currency: &str) -> String {
+    if currency == DEFAULT_CURRENCY {
+        "ecosystem:".to_string()
+    } else {
+        format!("ecosystem:currency:{}:", currency)
+    }
+}
+
+fn account_key_prefix(currency: &str) -> String {
+    format!("{}account:", currency_key_prefix(currency))
+}
+
+pub fn account_key(currency: &str, user_id: &str) -> String {
+    format!("{}{}", account_key_prefix(currency), user_id)
+}
+
+pub fn history_key(currency: &str, user_id: &str) -> String {
+    format!("{}history:{}", currency_key_prefix(currency), user_id)
+}
+
+pub fn leaderboard_key(currency: &str) -> String {
+    format!("{}leaderboard", currency_key_prefix(currency))
+}
+
+// 交易id在所有货币间唯一
+pub fn transaction_key(transaction_id: &str) -> String {
+    format!("ecosystem:transaction:{}", transaction_id)
+}
+
+// 一次带幂等key的请求
+// 幂等key按客户端与操作隔离, 并记录请求内容的摘要, 同一个key用于不同的请求时拒绝执行
+pub struct Idempotency {
+    // 客户端传入的幂等key
+    pub key: String,
+    pub redis_key: String,
+    pub request_hash: String,
+    pub ttl_seconds: usize,
+}
+
+impl Idempotency {
+    pub fn new(
+        client: &ClientIdentity,
+        operation: &str,
+        key: &str,
+        request: &serde_json::Value,
+    ) -> Self {
+        Idempotency {
+            key: key.to_string(),
+            redis_key: format!(
+                "ecosystem:idempotency:{}:{}:{}",
+                client.name, operation, key
+            ),
+            // 未开启 preserve_order 时对象按key排序, 相同内容的请求序列化结果一致
+            request_hash: sha1_smol::Sha1::from(request.to_string())
+                .digest()
+                .to_string(),
+            ttl_seconds: IDEMPOTENCY_KEY_TTL_SECONDS,
+        }
+    }
+
+    // 使用非默认的幂等记录保留时间
+    pub fn with_ttl(mut self, ttl_seconds: usize) -> Self {
+        self.ttl_seconds = ttl_seconds;
+        self
+    }
+
+    // 随首次执行结果一起保存的内容
+    pub fn store<T: Serialize>(&self, result: &T) -> String {
+        serde_json::to_string(&StoredIdempotentResult {
+            request_hash: self.request_hash.clone(),
+            result: serde_json::to_value(result).unwrap(),
+        })
+        .unwrap()
+    }
+
+    // 从保存的内容中取出首次执行的结果
+    pub fn replay<T: DeserializeOwned>(&self, raw: &str) -> Result<T, FineError> {
+        let stored: StoredIdempotentResult =
+            serde_json::from_str(raw).map_err(FineError::BrokenRecord)?;
+        if stored.request_hash != self.request_hash {
+            return Err(FineError::IdempotencyKeyReused(self.key.clone()));
+        }
+        serde_json::from_value(stored.result).map_err(FineError::BrokenRecord)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredIdempotentResult {
+    request_hash: String,
+    result: serde_json::Value,
+}
+
+fn parse_account(raw: &str) -> Result<StoredAccountRecord, FineError> {
+    serde_json::from_str(raw).map_err(FineError::BrokenRecord)
+}
+
+// 读取一个用户的账户, 不存在时返回None
+pub async fn get_account(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+    user_id: &str,
+) -> Result<Option<EcosystemUserAccountRecord>, FineError> {
+    let raw: Option<String> = redis_conn.get(account_key(currency, user_id)).await?;
+    Ok(raw
+        .map(|raw| parse_account(&raw))
+        .transpose()?
+        .map(|stored| stored.account))
+}
+
+// 从新到旧分页读取一个用户的资产变动记录
+// cursor 为从最旧记录起算的下标, 本页只读取下标小于它的记录(None 从最新的记录开始);
+// 记录只在末尾追加, 翻页期间新增的记录不会使后续页错位
+// 返回本页记录以及下一页的cursor(没有更多时为None)
+// 每次最多检查 limit * HISTORY_SCAN_FACTOR 条记录, 达到上限时即使本页不满也返回cursor
+pub async fn get_history_page(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+    user_id: &str,
+    cursor: Option<usize>,
+    limit: usize,
+    filter: &HistoryFilter<'_>,
+) -> Result<(Vec<EcosystemUserCreditAlterRecord>, Option<usize>), FineError> {
+    let key = history_key(currency, user_id);
+    let mut records = vec![];
+    let mut end: usize = match cursor {
+        Some(cursor) => cursor,
+        None => redis_conn.llen(&key).await?,
+    };
+    let scan_end = end.saturating_sub(limit.saturating_mul(HISTORY_SCAN_FACTOR));
+    while records.len() < limit && end > scan_end {
+        let start = end.saturating_sub(HISTORY_SCAN_CHUNK).max(scan_end);
+        let raws: Vec<String> = redis_conn
+            .lrange(&key, start as isize, end as isize - 1)
+            .await?;
+        if raws.is_empty() {
+            return Ok((records, None));
+        }
+        for raw in raws.iter().rev() {
+            let record: EcosystemUserCreditAlterRecord =
+                serde_json::from_str(raw).map_err(FineError::BrokenRecord)?;
+            end -= 1;
+            // 记录按时间顺序追加, 早于起始时间后不会再有符合条件的记录
+            if filter.since.is_some_and(|since| record.time < since) {
+                return Ok((records, None));
+            }
+            let after_until = filter.until.is_some_and(|until| record.time > until);
+            let unmatched = filter.kind.is_some_and(|kind| record.kind != kind)
+                || filter.counterparty.is_some_and(|counterparty| {
+                    record.counterparty.as_deref() != Some(counterparty)
+                })
+                || filter.reason_prefix.is_some_and(|prefix| {
+                    !record
+                        .metadata
+                        .get("reason")
+                        .is_some_and(|reason| reason.starts_with(prefix))
+                });
+            if !after_until && !unmatched {
+                records.push(record);
+                if records.len() == limit {
+                    break;
+                }
+            }
+        }
+    }
+    Ok((records, (end > 0).then_some(end)))
+}
+
+// 原子地修改一组账户
+// update 拿到各账户的当前记录, 修改账户并填入需要追加的资产变动记录;
+// 若期间有其他请求修改了这些账户, 则重新读取并再次调用 update
+// 传入幂等key时, update 的结果会随账户一起保存, 同一个key的重复请求直接返回首次的结果
+// 带有交易id的新记录会按交易id汇总, 与账户一起写入交易记录
+pub async fn update_accounts<T, F>(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+    user_ids: &[&str],
+    idempotency: Option<&Idempotency>,
+    mut update: F,
+) -> Result<T, FineError>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnMut(&mut [AccountUpdate]) -> Result<T, FineError>,
+{
+    update_accounts_linked(
+        redis_conn,
+        currency,
+        user_ids,
+        None,
+        idempotency,
+        |accounts, _| update(accounts),
+    )
+    .await
+}
+
+// 与 update_accounts 相同, 同时读取一条已有的交易记录交给 update 修改(不存在为None),
+// 该交易记录与账户一起参与比较并写入
+pub async fn update_accounts_linked<T, F>(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+    user_ids: &[&str],
+    linked_transaction_id: Option<&str>,
+    idempotency: Option<&Idempotency>,
+    mut update: F,
+) -> Result<T, FineError>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnMut(&mut [AccountUpdate], Option<&mut EcosystemTransactionRecord>) -> Result<T, FineError>,
+{
+    let account_keys: Vec<String> = user_ids
+        .iter()
+        .map(|id| account_key(currency, id))
+        .collect();
+    let linked_key = linked_transaction_id.map(transaction_key);
+    let script = Script::new(COMPARE_AND_SET_SCRIPT);
+    for attempt in 0..MAX_UPDATE_RETRIES {
+        let mut read_keys = account_keys.clone();
+        read_keys.extend(linked_key.clone());
+        read_keys.extend(idempotency.map(|idempotency| idempotency.redis_key.clone()));
+        let mut raws: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&read_keys)
+            .query_async(redis_conn)
+            .await?;
+        if let Some(idempotency) = idempotency {
+            if let Some(replay) = raws.pop().flatten() {
+                return idempotency.replay(&replay);
+            }
+        }
+        let linked_raw = match linked_key {
+            Some(_) => raws.pop().flatten(),
+            None => None,
+        };
+        let mut linked = linked_raw
+            .as_deref()
+            .map(serde_json::from_str::<EcosystemTransactionRecord>)
+            .transpose()
+            .map_err(FineError::BrokenRecord)?;
+        let mut updates = Vec::with_capacity(raws.len());
+        let mut legacy_records = Vec::with_capacity(raws.len());
+        for raw in raws.iter() {
+            let stored = raw.as_deref().map(parse_account).transpose()?;
+            let (account, legacy) = match stored {
+                Some(stored) => (Some(stored.account), stored.alter_records),
+                None => (None, None),
+            };
+            updates.push(AccountUpdate {
+                account,
+                new_records: vec![],
+            });
+            legacy_records.push(legacy.unwrap_or_default());
+        }
+        let before: Vec<Option<(i64, EcosystemAccountStatus)>> = updates
+            .iter()
+            .map(|update| update.account.as_ref().map(|a| (a.credit, a.status)))
+            .collect();
+        let result = update(&mut updates, linked.as_mut())?;
+
+        // (交易记录key, 读取时的旧值, 新值)
+        let mut transactions: Vec<(String, String, String)> = vec![];
+        if let (Some(key), Some(record)) = (&linked_key, &linked) {
+            transactions.push((
+                key.clone(),
+                linked_raw.clone().unwrap_or_default(),
+                serde_json::to_string(record).unwrap(),
+            ));
+        }
+        for record in collect_transactions(currency, user_ids, &updates) {
+            transactions.push((
+                transaction_key(&record.transaction_id),
+                String::new(),
+                serde_json::to_string(&record).unwrap(),
+            ));
+        }
+
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .arg(user_ids.len())
+            .arg(transactions.len())
+            .arg(idempotency.map_or(0, |idempotency| idempotency.ttl_seconds))
+            .arg(idempotency.is_some() as i32);
+        for key in account_keys.iter() {
+            invocation.key(key);
+        }
+        for user_id in user_ids.iter() {
+            invocation.key(history_key(currency, user_id));
+        }
+        invocation.key(leaderboard_key(currency));
+        for (((user_id, raw), update), legacy) in user_ids
+            .iter()
+            .zip(raws.iter())
+            .zip(updates.iter())
+            .zip(legacy_records)
+        {
+            // 旧格式中内嵌的记录与账户改写在同一次写入中迁移到记录列表
+            let records = legacy.iter().chain(update.new_records.iter());
+            invocation
+                .arg(raw.as_deref().unwrap_or_default())
+                .arg(match &update.account {
+                    Some(account) => serde_json::to_string(account).unwrap(),
+                    None => String::new(),
+                })
+                .arg(user_id)
+                .arg(match &update.account {
+                    Some(account) if account.status != EcosystemAccountStatus::Closed => {
+                        account.credit.to_string()
+                    }
+                    _ => String::new(),
+                })
+                .arg(legacy.len() + update.new_records.len());
+            for record in records {
+                invocation.arg(serde_json::to_string(record).unwrap());
+            }
+        }
+        for (key, old, new) in transactions.iter() {
+            invocation.key(key).arg(old).arg(new);
+        }
+        if let Some(idempotency) = idempotency {
+            invocation
+                .key(&idempotency.redis_key)
+                .arg(idempotency.store(&result));
+        }
+        let applied: i32 = invocation.invoke_async(redis_conn).await?;
+        if applied == 1 {
+            publish_credit_changed(redis_conn, currency, user_ids, &before, &updates).await;
+            return Ok(result);
+        }
+        tokio::time::sleep(conflict_backoff(attempt)).await;
+    }
+    Err(FineError::TooManyConflicts)
+}
+
+// 第 attempt 次冲突后的等待时间, 在 [0, 上限] 内均匀随机
+pub fn conflict_backoff(attempt: u32) -> Duration {
+    let cap = CONFLICT_BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(CONFLICT_BACKOFF_MAX);
+    cap.mul_f64(rand::random::<f64>())
+}
+
+// 向所有服务实例发布本次修改中余额或状态发生变化的账户
+// 修改已经写入, 发布失败只记录日志
+async fn publish_credit_changed(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+    user_ids: &[&str],
+    before: &[Option<(i64, EcosystemAccountStatus)>],
+    updates: &[AccountUpdate],
+) {
+    let now = chrono::Utc::now().timestamp();
+    let mut pipe = redis::pipe();
+    let mut published = 0;
+    for ((user_id, before), update) in user_ids.iter().zip(before).zip(updates) {
+        let Some(account) = &update.account else {
+            continue;
+        };
+        let (old_credit, old_status) = before.unwrap_or((0, account.status));
+        if update.new_records.is_empty()
+            && old_credit == account.credit
+            && old_status == account.status
+        {
+            continue;
+        }
+        let event = EcosystemCreditChangedEvent {
+            currency: currency.to_string(),
+            user_id: user_id.to_string(),
+            credit: account.credit,
+            delta: account.credit.saturating_sub(old_credit),
+            status: account.status,
+            transaction_id: update
+                .new_records
+                .iter()
+                .map(|record| record.transaction_id.clone())
+                .find(|transaction_id| !transaction_id.is_empty()),
+            time: now,
+        };
+        pipe.cmd("PUBLISH")
+            .arg(CREDIT_CHANGED_CHANNEL)
+            .arg(serde_json::to_string(&event).unwrap())
+            .ignore();
+        published += 1;
+    }
+    if published == 0 {
+        return;
+    }
+    if let Err(err) = pipe.query_async::<_, ()>(redis_conn).await {
+        warn!("Failed to publish credit change events: {}", err);
+    }
+}
+
+// 把本次修改中带有交易id的新记录按交易id汇总成交易记录
+pub fn collect_transactions(
+    currency: &str,
+    user_ids: &[&str],
+    updates: &[AccountUpdate],
+) -> Vec<EcosystemTransactionRecord> {
+    let mut transactions: Vec<EcosystemTransactionRecord> = vec![];
+    for (user_id, update) in user_ids.iter().zip(updates.iter()) {
+        for record in update.new_records.iter() {
+            if record.transaction_id.is_empty() {
+                continue;
+            }
+            let entry = EcosystemTransactionEntry {
+                user_id: user_id.to_string(),
+                credit: record.credit,
+                kind: record.kind,
+                counterparty: record.counterparty.clone(),
+                previous_credit: record.previous_credit,
+            };
+            match transactions
+                .iter_mut()
+                .find(|t| t.transaction_id == record.transaction_id)
+            {
+                Some(transaction) => transaction.entries.push(entry),
+                None => transactions.push(EcosystemTransactionRecord {
+                    transaction_id: record.transaction_id.clone(),
+                    currency: currency.to_string(),
+                    time: record.time,
+                    entries: vec![entry],
+                    reverses: record.reverses.clone(),
+                    reversed_by: None,
+                }),
+            }
+        }
+    }
+    transactions
+}
+
+// 读取一条交易记录, 不存在时返回None
+pub async fn get_transaction(
+    redis_conn: &mut MultiplexedConnection,
+    transaction_id: &str,
+) -> Result<Option<EcosystemTransactionRecord>, FineError> {
+    let raw: Option<String> = redis_conn.get(transaction_key(transaction_id)).await?;
+    raw.map(|raw| serde_json::from_str(&raw))
+        .transpose()
+        .map_err(FineError::BrokenRecord)
+}
+
+// 列出redis中所有账户的用户id
+async fn scan_account_user_ids(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+) -> Result<Vec<String>, FineError> {
+    let prefix = account_key_prefix(currency);
+    let mut user_ids: Vec<String> = vec![];
+    let mut cursor: u64 = 0;
+    loop {
+        let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(format!("{}*", prefix))
+            .arg("COUNT")
+            .arg(1000)
+            .query_async(redis_conn)
+            .await?;
+        user_ids.extend(batch.iter().map(|key| key[prefix.len()..].to_string()));
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    Ok(user_ids)
+}
+
+// 旧格式账户全部迁移后写入的标记, 之后启动时跳过扫描
+const LEGACY_MIGRATION_DONE_KEY: &str = "ecosystem:migration:legacy_accounts";
+
+// 把资产变动记录仍内嵌在账户中的旧格式账户迁移到独立的记录列表, 返回迁移的账户数
+// 旧格式只存在于默认货币; 无法解析或迁移失败的账户记录日志后跳过,
+// 这些账户在下次被修改时仍会由 update_accounts 迁移
+pub async fn migrate_legacy_accounts(
+    redis_conn: &mut MultiplexedConnection,
+) -> Result<usize, FineError> {
+    if redis_conn.exists(LEGACY_MIGRATION_DONE_KEY).await? {
+        return Ok(0);
+    }
+    let mut migrated = 0;
+    for user_id in scan_account_user_ids(redis_conn, DEFAULT_CURRENCY).await? {
+        let raw: Option<String> = redis_conn
+            .get(account_key(DEFAULT_CURRENCY, &user_id))
+            .await?;
+        let is_legacy = match raw.as_deref().map(parse_account).transpose() {
+            Ok(account) => account.is_some_and(|account| account.alter_records.is_some()),
+            Err(err) => {
+                warn!("Skipped unreadable account {}: {}", user_id, err);
+                continue;
+            }
+        };
+        if is_legacy {
+            // 空修改即可触发 update_accounts 中的迁移
+            match update_accounts(redis_conn, DEFAULT_CURRENCY, &[&user_id], None, |_| Ok(())).await
+            {
+                Ok(()) => migrated += 1,
+                Err(err @ FineError::Storage(_)) => return Err(err),
+                Err(err) => warn!("Skipped migrating account {}: {}", user_id, err),
+            }
+        }
+    }
+    let _: () = redis_conn.set(LEGACY_MIGRATION_DONE_KEY, 1).await?;
+    Ok(migrated)
+}
+
+// 按现有账户重建一个货币的排行榜, 返回排行榜中的用户数
+pub async fn rebuild_leaderboard(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+) -> Result<usize, FineError> {
+    let script = Script::new(SYNC_LEADERBOARD_SCRIPT);
+    // 排行榜中已经没有对应账户的用户也需要逐个修正
+    let mut user_ids: Vec<String> = redis_conn.zrange(leaderboard_key(currency), 0, -1).await?;
+    user_ids.extend(scan_account_user_ids(redis_conn, currency).await?);
+    user_ids.sort();
+    user_ids.dedup();
+    for user_id in user_ids.iter() {
+        let _: i64 = script
+            .key(account_key(currency, user_id))
+            .key(leaderboard_key(currency))
+            .arg(user_id)
+            .invoke_async(redis_conn)
+            .await?;
+    }
+    Ok(redis_conn.zcard(leaderboard_key(currency)).await?)
+}
+
+// 读取一个货币排行榜的前 top 名, 返回 (用户id, 余额)
+// 排行榜的分数为双精度浮点数, 绝对值超过 2^53 的余额在排行榜中会损失精度
+pub async fn get_leaderboard_top(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+    top: usize,
+) -> Result<Vec<(String, i64)>, FineError> {
+    if top == 0 {
+        return Ok(vec![]);
+    }
+    let entries: Vec<(String, f64)> = redis_conn
+        .zrevrange_withscores(leaderboard_key(currency), 0, top as isize - 1)
+        .await?;
+    Ok(entries
+        .into_iter()
+        .map(|(user_id, score)| (user_id, score as i64))
+        .collect())
+}
+
+// 读取一个用户在排行榜中的名次(从0开始)与余额
+pub async fn get_leaderboard_rank(
+    redis_conn: &mut MultiplexedConnection,
+    currency: &str,
+    user_id: &str,
+) -> Result<Option<(usize, i64)>, FineError> {
+    let rank: Option<usize> = redis_conn
+        .zrevrank(leaderboard_key(currency), user_id)
+        .await?;
+    let score: Option<f64> = redis_conn
+        .zscore(leaderboard_key(currency), user_id)
+        .await?;
+    Ok(rank.zip(score.map(|score| score as i64)))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,167 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde_json::json;
+
+use super::*;
+use crate::{
+    auth::ClientIdentity, config::EconomyConfig, handler::ecosystem::transfer_user_credit,
+};
+
+// 需要一个可写的 redis, 默认不运行, CI 中连接 redis 服务运行:
+// TEST_REDIS_URL=redis://127.0.0.1/ cargo test -- --ignored
+// 使用随机的货币名隔离数据, 结束后删除该货币下的所有key与产生的交易记录
+
+const ACCOUNTS: usize = 8;
+const INITIAL_CREDIT: i64 = 1000;
+const TRANSFERS: usize = 400;
+
+async fn connect() -> MultiplexedConnection {
+    let url = std::env::var("TEST_REDIS_URL").unwrap_or("redis://127.0.0.1/".to_string());
+    redis::Client::open(url)
+        .unwrap()
+        .get_multiplexed_async_connection()
+        .await
+        .expect("failed to connect to redis")
+}
+
+async fn delete_currency(redis_conn: &mut MultiplexedConnection, currency: &str) {
+    let keys: Vec<String> = redis_conn
+        .keys(format!("{}*", currency_key_prefix(currency)))
+        .await
+        .unwrap();
+    if !keys.is_empty() {
+        let _: () = redis_conn.del(keys).await.unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+#[ignore = "requires redis"]
+async fn concurrent_transfers_keep_total_credit() {
+    let mut redis_conn = connect().await;
+    let currency = format!("test-{:016x}", rand::random::<u64>());
+    let user_ids: Vec<String> = (0..ACCOUNTS).map(|i| format!("user{}", i)).collect();
+    for user_id in user_ids.iter() {
+        let _: () = redis_conn
+            .set(
+                account_key(&currency, user_id),
+                serde_json::to_string(&EcosystemUserAccountRecord::new(INITIAL_CREDIT)).unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    let config = Arc::new(EconomyConfig {
+        currencies: HashMap::from([(currency.clone(), Default::default())]),
+    });
+    let identity = ClientIdentity {
+        name: "concurrency-test".to_string(),
+        scopes: HashSet::new(),
+        alter_limit: None,
+    };
+    let mut tasks = vec![];
+    for i in 0..TRANSFERS {
+        let mut redis_conn = redis_conn.clone();
+        let (config, identity) = (config.clone(), identity.clone());
+        let request = json!({
+            "currency": currency,
+            "from_user_id": user_ids[i % ACCOUNTS],
+            "to_user_id": user_ids[(i * 3 + 1) % ACCOUNTS],
+            "credit": (i % 50) as i64 + 1,
+        });
+        tasks.push(tokio::spawn(async move {
+            transfer_user_credit(request, &mut redis_conn, &config, &identity).await
+        }));
+    }
+    let mut transaction_ids = vec![];
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(resp) => transaction_ids.push(transaction_key(
+                resp.data["transaction_id"].as_str().unwrap(),
+            )),
+            Err(FineError::InsufficientCredit | FineError::SelfTransfer) => {}
+            Err(err) => panic!("transfer failed: {}", err),
+        }
+    }
+    let succeeded = transaction_ids.len();
+    if !transaction_ids.is_empty() {
+        let _: () = redis_conn.del(&transaction_ids).await.unwrap();
+    }
+
+    let mut total = 0;
+    for user_id in user_ids.iter() {
+        let account = get_account(&mut redis_conn, &currency, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        // 每个账户的余额与其资产变动记录一致
+        let raws: Vec<String> = redis_conn
+            .lrange(history_key(&currency, user_id), 0, -1)
+            .await
+            .unwrap();
+        let recorded: i64 = raws
+            .iter()
+            .map(|raw| {
+                serde_json::from_str::<EcosystemUserCreditAlterRecord>(raw)
+                    .unwrap()
+                    .credit
+            })
+            .sum();
+        assert_eq!(account.credit, INITIAL_CREDIT + recorded);
+        assert!(account.credit >= 0);
+        total += account.credit;
+    }
+    delete_currency(&mut redis_conn, &currency).await;
+    assert_eq!(total, INITIAL_CREDIT * ACCOUNTS as i64);
+    assert!(succeeded >= TRANSFERS / 2, "only {} succeeded", succeeded);
+}
+
+#[tokio::test]
+#[ignore = "requires redis"]
+async fn history_page_stops_at_scan_limit() {
+    let mut redis_conn = connect().await;
+    let currency = format!("test-{:016x}", rand::random::<u64>());
+    let limit = 2;
+    let total = limit * HISTORY_SCAN_FACTOR + 10;
+    let records: Vec<String> = (0..total)
+        .map(|i| {
+            serde_json::to_string(&EcosystemUserCreditAlterRecord {
+                time: i as i64,
+                credit: 1,
+                kind: EcosystemCreditChangeKind::Alter,
+                counterparty: None,
+                source_client: None,
+                metadata: HashMap::new(),
+                transaction_id: String::new(),
+                reverses: None,
+                previous_credit: None,
+            })
+            .unwrap()
+        })
+        .collect();
+    let _: () = redis_conn
+        .rpush(history_key(&currency, "user"), records)
+        .await
+        .unwrap();
+
+    // 没有符合条件的记录时, 检查到上限即返回空页与下一页的cursor
+    let filter = HistoryFilter {
+        since: None,
+        until: None,
+        kind: Some(EcosystemCreditChangeKind::Reward),
+        counterparty: None,
+        reason_prefix: None,
+    };
+    let first = get_history_page(&mut redis_conn, &currency, "user", None, limit, &filter).await;
+    let second =
+        get_history_page(&mut redis_conn, &currency, "user", Some(10), limit, &filter).await;
+    delete_currency(&mut redis_conn, &currency).await;
+    let (records, next) = first.unwrap();
+    assert!(records.is_empty());
+    assert_eq!(next, Some(10));
+    let (records, next) = second.unwrap();
+    assert!(records.is_empty());
+    assert_eq!(next, None);
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::FineError,
+    model::ecosystem::{
+        EcosystemTransactionRecord, EcosystemUserAccountRecord, EcosystemUserCreditAlterRecord,
+    },
+    storage::{
+        ecosystem::{
+            collect_transactions, conflict_backoff, AccountUpdate, Idempotency, MAX_UPDATE_RETRIES,
+        },
+        store::AccountStore,
+    },
+};
+
+// 内存中的账户存储, 供测试使用
+// 克隆出的存储共享同一份数据, 与 redis 连接一样可以在多个任务中并发修改
+#[derive(Default, Clone)]
+pub struct MemoryAccountStore {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    // (货币, 用户id) -> 账户
+    accounts: HashMap<(String, String), EcosystemUserAccountRecord>,
+    histories: HashMap<(String, String), Vec<EcosystemUserCreditAlterRecord>>,
+    transactions: HashMap<String, EcosystemTransactionRecord>,
+    // 幂等key -> 首次执行结果的json
+    idempotency: HashMap<String, String>,
+}
+
+fn store_key(currency: &str, user_id: &str) -> (String, String) {
+    (currency.to_string(), user_id.to_string())
+}
+
+// 比较时使用序列化后的内容, 与 redis 实现比较原始值相同
+fn snapshot<T: Serialize>(value: Option<&T>) -> Option<String> {
+    value.map(|value| serde_json::to_string(value).unwrap())
+}
+
+impl MemoryAccountStore {
+    // 一个用户的资产变动记录, 从旧到新排列
+    pub fn history(&self, currency: &str, user_id: &str) -> Vec<EcosystemUserCreditAlterRecord> {
+        self.state
+            .lock()
+            .unwrap()
+            .histories
+            .get(&store_key(currency, user_id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // 直接写入一个账户, 用于准备测试数据
+    pub fn insert_account(
+        &self,
+        currency: &str,
+        user_id: &str,
+        account: EcosystemUserAccountRecord,
+    ) {
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .insert(store_key(currency, user_id), account);
+    }
+
+    pub fn transaction(&self, transaction_id: &str) -> Option<EcosystemTransactionRecord> {
+        self.state
+            .lock()
+            .unwrap()
+            .transactions
+            .get(transaction_id)
+            .cloned()
+    }
+}
+
+impl MemoryState {
+    // 读取的内容都未被修改时写入本次修改, 返回是否写入
+    fn compare_and_set(
+        &mut self,
+        currency: &str,
+        user_ids: &[&str],
+        before: &[Option<String>],
+        updates: &[AccountUpdate],
+        // (交易id, 读取时的内容, 修改后的交易记录)
+        linked: Option<(&str, Option<String>, Option<EcosystemTransactionRecord>)>,
+        idempotency: Option<(&Idempotency, String)>,
+    ) -> bool {
+        let unchanged = user_ids.iter().zip(before.iter()).all(|(user_id, before)| {
+            snapshot(self.accounts.get(&store_key(currency, user_id))) == *before
+        }) && linked.as_ref().is_none_or(|(transaction_id, before, _)| {
+            snapshot(self.transactions.get(*transaction_id)) == *before
+        }) && idempotency
+            .as_ref()
+            .is_none_or(|(idempotency, _)| !self.idempotency.contains_key(&idempotency.redis_key));
+        if !unchanged {
+            return false;
+        }
+        for (user_id, update) in user_ids.iter().zip(updates.iter()) {
+            let key = store_key(currency, user_id);
+            if let Some(account) = &update.account {
+                self.accounts.insert(key.clone(), account.clone());
+            }
+            self.histories
+                .entry(key)
+                .or_default()
+                .extend_from_slice(&update.new_records);
+        }
+        let new_transactions = collect_transactions(currency, user_ids, updates);
+        let linked = linked.and_then(|(_, _, linked)| linked);
+        for transaction in linked.into_iter().chain(new_transactions) {
+            self.transactions
+                .insert(transaction.transaction_id.clone(), transaction);
+        }
+        if let Some((idempotency, stored)) = idempotency {
+            self.idempotency
+                .insert(idempotency.redis_key.clone(), stored);
+        }
+        true
+    }
+}
+
+impl AccountStore for MemoryAccountStore {
+    async fn get_account(
+        &mut self,
+        currency: &str,
+        user_id: &str,
+    ) -> Result<Option<EcosystemUserAccountRecord>, FineError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.accounts.get(&store_key(currency, user_id)).cloned())
+    }
+
+    async fn get_transaction(
+        &mut self,
+        transaction_id: &str,
+    ) -> Result<Option<EcosystemTransactionRecord>, FineError> {
+        Ok(self.transaction(transaction_id))
+    }
+
+    // 与 redis 实现相同的乐观并发: 读取后让出执行权, 写入前比较读取的内容, 被修改过则退避后重试
+    async fn update_accounts_linked<T, F>(
+        &mut self,
+        currency: &str,
+        user_ids: &[&str],
+        linked_transaction_id: Option<&str>,
+        idempotency: Option<&Idempotency>,
+        mut update: F,
+    ) -> Result<T, FineError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(
+            &mut [AccountUpdate],
+            Option<&mut EcosystemTransactionRecord>,
+        ) -> Result<T, FineError>,
+    {
+        for attempt in 0..MAX_UPDATE_RETRIES {
+            let (mut updates, mut linked) = {
+                let state = self.state.lock().unwrap();
+                // 重复请求返回经过序列化保存的首次结果
+                if let Some(idempotency) = idempotency {
+                    if let Some(replay) = state.idempotency.get(&idempotency.redis_key) {
+                        return idempotency.replay(replay);
+                    }
+                }
+                let updates: Vec<AccountUpdate> = user_ids
+                    .iter()
+                    .map(|user_id| AccountUpdate {
+                        account: state.accounts.get(&store_key(currency, user_id)).cloned(),
+                        new_records: vec![],
+                    })
+                    .collect();
+                let linked = linked_transaction_id
+                    .and_then(|transaction_id| state.transactions.get(transaction_id).cloned());
+                (updates, linked)
+            };
+            let before: Vec<Option<String>> = updates
+                .iter()
+                .map(|update| snapshot(update.account.as_ref()))
+                .collect();
+            let linked_before = snapshot(linked.as_ref());
+            tokio::task::yield_now().await;
+            let result = update(&mut updates, linked.as_mut())?;
+
+            let written = self.state.lock().unwrap().compare_and_set(
+                currency,
+                user_ids,
+                &before,
+                &updates,
+                linked_transaction_id.map(|transaction_id| (transaction_id, linked_before, linked)),
+                idempotency.map(|idempotency| (idempotency, idempotency.store(&result))),
+            );
+            if written {
+                return Ok(result);
+            }
+            tokio::time::sleep(conflict_backoff(attempt)).await;
+        }
+        Err(FineError::TooManyConflicts)
+    }
+}
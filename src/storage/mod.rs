@@ -0,0 +1,7 @@
+// 存储层, 封装对redis的读写
+pub mod ecosystem;
+#[cfg(test)]
+pub mod memory;
+pub mod pool;
+pub mod schedule;
+pub mod store;
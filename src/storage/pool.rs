@@ -0,0 +1,141 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use redis::{aio::MultiplexedConnection, RedisError};
+use tokio::sync::Mutex;
+use tracing::{info, log::warn};
+
+use crate::error::FineError;
+
+// 建立单个连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 连接失败与临时错误的重试配置
+pub struct RetryConfig {
+    pub max_retries: u32,
+    // 第一次重试前的等待时间, 之后每次翻倍
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryConfig {
+    // 第 attempt 次重试前的等待时间
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+// 是否为连接层面的临时错误, 换一条连接重试可能成功
+pub fn is_transient(err: &RedisError) -> bool {
+    err.is_io_error()
+        || err.is_connection_dropped()
+        || err.is_connection_refusal()
+        || err.is_timeout()
+}
+
+struct Slot {
+    // 每次重连后递增, 避免把别人刚重建的连接当作坏连接丢弃
+    generation: u64,
+    conn: MultiplexedConnection,
+}
+
+// 从连接池取出的连接
+pub struct PooledConnection {
+    slot: usize,
+    generation: u64,
+    conn: MultiplexedConnection,
+}
+
+impl PooledConnection {
+    pub fn conn(&mut self) -> &mut MultiplexedConnection {
+        &mut self.conn
+    }
+}
+
+// 固定大小的 redis 连接池, 所有连接在请求间共享
+// 连接按需建立, 出现连接错误后在下次取用时自动重连
+pub struct RedisPool {
+    client: redis::Client,
+    slots: Vec<Mutex<Option<Slot>>>,
+    next_slot: AtomicUsize,
+    next_generation: AtomicU64,
+    pub retry: RetryConfig,
+}
+
+impl RedisPool {
+    pub fn new(client: redis::Client, size: usize, retry: RetryConfig) -> Self {
+        RedisPool {
+            client,
+            slots: (0..size.max(1)).map(|_| Mutex::new(None)).collect(),
+            next_slot: AtomicUsize::new(0),
+            next_generation: AtomicU64::new(0),
+            retry,
+        }
+    }
+
+    // 轮流取出一条连接, 连接不存在时带退避地重连, 重试耗尽时返回 StorageUnavailable
+    pub async fn get(&self) -> Result<PooledConnection, FineError> {
+        let slot = self.next_slot.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        // 重连期间持有锁, 同一槽位上的其他请求等待这次重连的结果
+        let mut guard = self.slots[slot].lock().await;
+        if let Some(existing) = guard.as_ref() {
+            return Ok(PooledConnection {
+                slot,
+                generation: existing.generation,
+                conn: existing.conn.clone(),
+            });
+        }
+        let mut attempt = 0;
+        let conn = loop {
+            let err = match tokio::time::timeout(
+                CONNECT_TIMEOUT,
+                self.client.get_multiplexed_async_connection(),
+            )
+            .await
+            {
+                Ok(Ok(conn)) => break conn,
+                Ok(Err(err)) if !is_transient(&err) => return Err(FineError::Storage(err)),
+                Ok(Err(err)) => err.to_string(),
+                Err(_) => "connect timed out".to_string(),
+            };
+            if attempt >= self.retry.max_retries {
+                warn!("Failed to connect to redis, giving up: {}", err);
+                return Err(FineError::StorageUnavailable);
+            }
+            let delay = self.retry.backoff(attempt);
+            warn!(
+                "Failed to connect to redis, retrying in {:?}: {}",
+                delay, err
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        info!("Redis connection {} established", slot);
+        *guard = Some(Slot {
+            generation,
+            conn: conn.clone(),
+        });
+        Ok(PooledConnection {
+            slot,
+            generation,
+            conn,
+        })
+    }
+
+    // 使用连接时出现连接错误, 丢弃该连接以便下次取用时重连
+    pub async fn invalidate(&self, conn: &PooledConnection) {
+        let mut guard = self.slots[conn.slot].lock().await;
+        if guard
+            .as_ref()
+            .is_some_and(|existing| existing.generation == conn.generation)
+        {
+            warn!("Redis connection {} broken, dropping it", conn.slot);
+            *guard = None;
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use redis::{aio::MultiplexedConnection, AsyncCommands, Script};
+
+use crate::{
+    error::FineError,
+    model::ecosystem::{EcosystemScheduledPayment, EcosystemScheduledPaymentStatus},
+};
+
+// 按下次执行时间排序的任务id
+const SCHEDULE_DUE_KEY: &str = "ecosystem:schedule:due";
+// 按失败时间排序的执行失败的任务id
+const SCHEDULE_FAILED_KEY: &str = "ecosystem:schedule:failed";
+
+// 执行失败的任务的保留时间(7天)
+const FAILED_JOB_RETENTION_SECONDS: i64 = 7 * 24 * 3600;
+
+// 比较并写入一个任务, 同时维护到期索引与失败索引
+// KEYS[1]: 任务key, KEYS[2]: 到期索引, KEYS[3]: 失败索引
+// ARGV[1]: 读取时的旧值(不存在为空串), ARGV[2]: 新值(为空串时删除任务), ARGV[3]: 任务id,
+// ARGV[4]: 下次执行时间, 失败的任务为失败时间, ARGV[5]: 失败的任务的保留秒数, 其他任务为空串
+// 失败的任务到期后自动删除, 失败索引中过期的id在之后写入失败任务时清理
+const REPLACE_JOB_SCRIPT: &str = r#"
+local current = redis.call('GET', KEYS[1])
+if current == false then
+    current = ''
+end
+if current ~= ARGV[1] then
+    return 0
+end
+if ARGV[2] == '' then
+    redis.call('DEL', KEYS[1])
+    redis.call('ZREM', KEYS[2], ARGV[3])
+    redis.call('ZREM', KEYS[3], ARGV[3])
+elseif ARGV[5] == '' then
+    redis.call('SET', KEYS[1], ARGV[2])
+    redis.call('ZADD', KEYS[2], ARGV[4], ARGV[3])
+else
+    redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[5])
+    redis.call('ZREM', KEYS[2], ARGV[3])
+    redis.call('ZADD', KEYS[3], ARGV[4], ARGV[3])
+    redis.call('ZREMRANGEBYSCORE', KEYS[3], '-inf', '(' .. (tonumber(ARGV[4]) - tonumber(ARGV[5])))
+end
+return 1
+"#;
+
+fn job_key(job_id: &str) -> String {
+    format!("ecosystem:schedule:job:{}", job_id)
+}
+
+fn parse_job(raw: &str) -> Result<EcosystemScheduledPayment, FineError> {
+    serde_json::from_str(raw).map_err(FineError::BrokenRecord)
+}
+
+// 读取一个任务及其原始值, 原始值用于之后的比较并写入
+pub async fn get_job(
+    redis_conn: &mut MultiplexedConnection,
+    job_id: &str,
+) -> Result<Option<(String, EcosystemScheduledPayment)>, FineError> {
+    let raw: Option<String> = redis_conn.get(job_key(job_id)).await?;
+    match raw {
+        Some(raw) => {
+            let job = parse_job(&raw)?;
+            Ok(Some((raw, job)))
+        }
+        None => Ok(None),
+    }
+}
+
+// 当任务仍为 old_raw 时替换为 job(None 为删除), 返回是否写入成功
+pub async fn replace_job(
+    redis_conn: &mut MultiplexedConnection,
+    job_id: &str,
+    old_raw: Option<&str>,
+    job: Option<&EcosystemScheduledPayment>,
+) -> Result<bool, FineError> {
+    let failed = job.filter(|job| job.status == EcosystemScheduledPaymentStatus::Failed);
+    let score = match failed {
+        Some(job) => job.last_run_at.unwrap_or(job.next_run_at),
+        None => job.map(|job| job.next_run_at).unwrap_or_default(),
+    };
+    let applied: i32 = Script::new(REPLACE_JOB_SCRIPT)
+        .key(job_key(job_id))
+        .key(SCHEDULE_DUE_KEY)
+        .key(SCHEDULE_FAILED_KEY)
+        .arg(old_raw.unwrap_or_default())
+        .arg(
+            job.map(|job| serde_json::to_string(job).unwrap())
+                .unwrap_or_default(),
+        )
+        .arg(job_id)
+        .arg(score)
+        .arg(
+            failed
+                .map(|_| FAILED_JOB_RETENTION_SECONDS.to_string())
+                .unwrap_or_default(),
+        )
+        .invoke_async(redis_conn)
+        .await?;
+    Ok(applied == 1)
+}
+
+// 列出所有任务, 先按下次执行时间列出待执行的任务, 再按失败时间列出执行失败的任务
+pub async fn list_jobs(
+    redis_conn: &mut MultiplexedConnection,
+) -> Result<Vec<EcosystemScheduledPayment>, FineError> {
+    let mut job_ids: Vec<String> = redis_conn.zrange(SCHEDULE_DUE_KEY, 0, -1).await?;
+    let failed_ids: Vec<String> = redis_conn.zrange(SCHEDULE_FAILED_KEY, 0, -1).await?;
+    job_ids.extend(failed_ids);
+    if job_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let keys: Vec<String> = job_ids.iter().map(|id| job_key(id)).collect();
+    let raws: Vec<Option<String>> = redis::cmd("MGET")
+        .arg(&keys)
+        .query_async(redis_conn)
+        .await?;
+    raws.iter().flatten().map(|raw| parse_job(raw)).collect()
+}
+
+// 取出下次执行时间不晚于 now 的任务id, 最多 limit 个
+pub async fn due_job_ids(
+    redis_conn: &mut MultiplexedConnection,
+    now: i64,
+    limit: isize,
+) -> Result<Vec<String>, FineError> {
+    Ok(redis_conn
+        .zrangebyscore_limit(SCHEDULE_DUE_KEY, "-inf", now, 0, limit)
+        .await?)
+}
@@ -0,0 +1,101 @@
+use redis::aio::MultiplexedConnection;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::FineError,
+    model::ecosystem::{EcosystemTransactionRecord, EcosystemUserAccountRecord},
+    storage::ecosystem::{self, AccountUpdate, Idempotency},
+};
+
+// 账户存储, 余额相关的处理只通过这里读写账户, 测试时替换为内存实现
+pub trait AccountStore {
+    // 读取一个用户的账户, 不存在时返回None
+    async fn get_account(
+        &mut self,
+        currency: &str,
+        user_id: &str,
+    ) -> Result<Option<EcosystemUserAccountRecord>, FineError>;
+
+    // 读取一条交易记录, 不存在时返回None
+    async fn get_transaction(
+        &mut self,
+        transaction_id: &str,
+    ) -> Result<Option<EcosystemTransactionRecord>, FineError>;
+
+    // 原子地修改一组账户, 语义见 storage::ecosystem::update_accounts
+    async fn update_accounts<T, F>(
+        &mut self,
+        currency: &str,
+        user_ids: &[&str],
+        idempotency: Option<&Idempotency>,
+        mut update: F,
+    ) -> Result<T, FineError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(&mut [AccountUpdate]) -> Result<T, FineError>,
+    {
+        self.update_accounts_linked(currency, user_ids, None, idempotency, |accounts, _| {
+            update(accounts)
+        })
+        .await
+    }
+
+    // 原子地修改一组账户及一条已有的交易记录, 语义见 storage::ecosystem::update_accounts_linked
+    async fn update_accounts_linked<T, F>(
+        &mut self,
+        currency: &str,
+        user_ids: &[&str],
+        linked_transaction_id: Option<&str>,
+        idempotency: Option<&Idempotency>,
+        update: F,
+    ) -> Result<T, FineError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(
+            &mut [AccountUpdate],
+            Option<&mut EcosystemTransactionRecord>,
+        ) -> Result<T, FineError>;
+}
+
+impl AccountStore for MultiplexedConnection {
+    async fn get_account(
+        &mut self,
+        currency: &str,
+        user_id: &str,
+    ) -> Result<Option<EcosystemUserAccountRecord>, FineError> {
+        ecosystem::get_account(self, currency, user_id).await
+    }
+
+    async fn get_transaction(
+        &mut self,
+        transaction_id: &str,
+    ) -> Result<Option<EcosystemTransactionRecord>, FineError> {
+        ecosystem::get_transaction(self, transaction_id).await
+    }
+
+    async fn update_accounts_linked<T, F>(
+        &mut self,
+        currency: &str,
+        user_ids: &[&str],
+        linked_transaction_id: Option<&str>,
+        idempotency: Option<&Idempotency>,
+        update: F,
+    ) -> Result<T, FineError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnMut(
+            &mut [AccountUpdate],
+            Option<&mut EcosystemTransactionRecord>,
+        ) -> Result<T, FineError>,
+    {
+        ecosystem::update_accounts_linked(
+            self,
+            currency,
+            user_ids,
+            linked_transaction_id,
+            idempotency,
+            update,
+        )
+        .await
+    }
+}